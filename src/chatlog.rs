@@ -0,0 +1,177 @@
+use crate::chats::known_chats;
+use crate::config::config;
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use frankenstein::types::Message;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Stored instead of the text of users who opted out with `/privacidade off`.
+pub const REDACTED_TEXT: &str = "[mensagem oculta]";
+
+/// Days of log kept per chat unless `CHAT_LOG_RETENTION_DAYS` says otherwise.
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// One chat message, stored as a line of `{chat dir}/log/{YYYY-MM-DD}.jsonl`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+    /// RFC 3339, local time.
+    pub timestamp: String,
+    pub chat_id: i64,
+    pub user_id: Option<u64>,
+    pub username: Option<String>,
+    pub first_name: String,
+    pub message_id: i32,
+    pub text: String,
+    pub reply_to: Option<i32>,
+}
+
+impl LogEntry {
+    pub fn from_message(message: &Message, text: &str) -> Option<LogEntry> {
+        let user = message.from.as_deref()?;
+        Some(LogEntry {
+            timestamp: Local::now().to_rfc3339(),
+            chat_id: message.chat.id,
+            user_id: Some(user.id),
+            username: user.username.clone(),
+            first_name: user.first_name.clone(),
+            message_id: message.message_id,
+            text: text.to_string(),
+            reply_to: message.reply_to_message.as_ref().map(|reply| reply.message_id),
+        })
+    }
+
+    pub fn time(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.timestamp).ok().map(|t| t.with_timezone(&Local))
+    }
+
+    /// The line shown to the model, e.g. `[18/10 21:04] Ana: bora jogar?`.
+    pub fn render(&self) -> String {
+        let time = self.time().map(|t| t.format("%d/%m %H:%M").to_string()).unwrap_or_default();
+        format!("[{}] {}: {}", time, self.first_name, self.text)
+    }
+}
+
+fn log_dir(chat_id: i64) -> PathBuf {
+    config().chat_path(chat_id, &config().files.chat_logs)
+}
+
+fn retention_days() -> i64 {
+    env::var("CHAT_LOG_RETENTION_DAYS").ok().and_then(|days| days.parse().ok()).unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// The dated log files of a chat, oldest first.
+fn log_files(chat_id: i64) -> Vec<(NaiveDate, PathBuf)> {
+    let Ok(entries) = fs::read_dir(log_dir(chat_id)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(NaiveDate, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let date = NaiveDate::parse_from_str(entry.path().file_stem()?.to_str()?, "%Y-%m-%d").ok()?;
+            Some((date, entry.path()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Deletes the files that fell out of the retention window.
+fn prune(chat_id: i64) {
+    let oldest_kept = Local::now().date_naive() - Duration::days(retention_days() - 1);
+    for (date, path) in log_files(chat_id) {
+        if date < oldest_kept {
+            if let Err(err) = fs::remove_file(&path) {
+                println!("Failed to remove old chat log {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Appends an entry to today's file; starting a new day also prunes old days.
+pub fn append_entry(entry: &LogEntry) -> Result<(), String> {
+    let dir = log_dir(entry.chat_id);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(format!("{}.jsonl", Local::now().format("%Y-%m-%d")));
+    if !path.exists() {
+        prune(entry.chat_id);
+    }
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
+
+/// Every retained entry of a chat, oldest first. Lines that fail to parse are skipped.
+pub fn read_log(chat_id: i64) -> Vec<LogEntry> {
+    log_files(chat_id)
+        .into_iter()
+        .filter_map(|(_, path)| fs::read_to_string(path).ok())
+        .flat_map(|data| data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect::<Vec<LogEntry>>())
+        .collect()
+}
+
+/// Rewrites every log file of every chat, keeping what `keep` returns for each entry.
+/// Returns how many entries were changed or dropped.
+fn rewrite_entries(mut keep: impl FnMut(LogEntry) -> Option<LogEntry>) -> Result<usize, String> {
+    let mut touched = 0;
+    for chat_id in known_chats() {
+        for (_, path) in log_files(chat_id) {
+            let data = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            let mut lines = Vec::new();
+            let mut changed = false;
+            for line in data.lines() {
+                let Ok(entry) = serde_json::from_str::<LogEntry>(line) else {
+                    lines.push(line.to_string());
+                    continue;
+                };
+                let original = line.to_string();
+                match keep(entry) {
+                    Some(entry) => {
+                        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+                        if line != original {
+                            touched += 1;
+                            changed = true;
+                        }
+                        lines.push(line);
+                    }
+                    None => {
+                        touched += 1;
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                let data = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+                fs::write(&path, data).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(touched)
+}
+
+/// Blanks the text of everything a user said, keeping the entries for counts.
+pub fn redact_user(user_id: u64) -> Result<usize, String> {
+    rewrite_entries(|mut entry| {
+        if entry.user_id == Some(user_id) && entry.text != REDACTED_TEXT {
+            entry.text = REDACTED_TEXT.to_string();
+        }
+        Some(entry)
+    })
+}
+
+/// Deletes every entry of a user from all chat logs.
+pub fn forget_user(user_id: u64) -> Result<usize, String> {
+    rewrite_entries(|entry| (entry.user_id != Some(user_id)).then_some(entry))
+}
+
+/// The chats whose retained log has anything from a user.
+pub fn chats_with(user_id: u64) -> Vec<i64> {
+    known_chats().into_iter().filter(|chat_id| read_log(*chat_id).iter().any(|entry| entry.user_id == Some(user_id))).collect()
+}
+
+/// Everything a user said in the given chats, oldest first within each chat.
+pub fn entries_of(user_id: u64, chats: &[i64]) -> Vec<LogEntry> {
+    chats.iter().copied().flat_map(read_log).filter(|entry| entry.user_id == Some(user_id)).collect()
+}
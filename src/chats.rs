@@ -0,0 +1,159 @@
+use crate::config::config;
+use crate::digest::parse_weekday;
+use crate::persona::read_personas;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The team emoji used in calendario.json when a chat has not picked one.
+pub const DEFAULT_TEAM: &str = "🫀";
+
+/// Commands that can never be disabled, so a chat cannot lock itself out of `/config`.
+const ALWAYS_ENABLED: &[&str] = &["/config", "/comandos"];
+
+/// How the bot behaves in one chat, kept in chats.json by chat id.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChatSettings {
+    /// Team emoji whose games `/proximojogo` and `/calendario` show.
+    pub team: String,
+    pub persona: Option<String>,
+    /// Overrides the persona's `{idioma}` in this chat.
+    pub language: Option<String>,
+    pub disabled_commands: Vec<String>,
+    /// `HH:MM` at which the daily digest is posted; no digest when unset.
+    pub digest_time: Option<String>,
+    /// Weekday, e.g. `domingo`, on which a weekly digest is posted as well.
+    pub weekly_digest: Option<String>,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        ChatSettings {
+            team: DEFAULT_TEAM.to_string(),
+            persona: None,
+            language: None,
+            disabled_commands: Vec::new(),
+            digest_time: None,
+            weekly_digest: None,
+        }
+    }
+}
+
+impl ChatSettings {
+    pub fn is_enabled(&self, command: &str) -> bool {
+        !self.disabled_commands.iter().any(|c| c.eq_ignore_ascii_case(command))
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "⚙️ Configuração deste chat:\n\nTime: {}\nPersona: {}\nIdioma: {}\nComandos desativados: {}\nResumo diário: {}\nResumo semanal: {}",
+            self.team,
+            self.persona.as_deref().unwrap_or("padrão"),
+            self.language.as_deref().unwrap_or("o da persona"),
+            if self.disabled_commands.is_empty() { "nenhum".to_string() } else { self.disabled_commands.join(", ") },
+            self.digest_time.as_deref().unwrap_or("desligado"),
+            self.weekly_digest.as_deref().unwrap_or("desligado")
+        )
+    }
+}
+
+pub fn read_chat_settings() -> Result<HashMap<String, ChatSettings>, String> {
+    let data = fs::read_to_string(&config().files.chats).map_err(|e| e.to_string())?;
+    let settings: HashMap<String, ChatSettings> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(settings)
+}
+
+fn write_chat_settings(settings: &HashMap<String, ChatSettings>) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&config().files.chats, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn settings_for_chat(chat_id: i64) -> Result<ChatSettings, String> {
+    Ok(read_chat_settings()?.remove(&chat_id.to_string()).unwrap_or_default())
+}
+
+/// Applies an admin command such as `time 🐙` or `desativar /will` and returns the new settings.
+pub fn update_chat_settings(chat_id: i64, key: &str, value: &str) -> Result<ChatSettings, String> {
+    let mut all = read_chat_settings()?;
+    let settings = all.entry(chat_id.to_string()).or_default();
+    let reset = value.eq_ignore_ascii_case("padrao") || value.eq_ignore_ascii_case("padrão");
+    let off = reset || value.eq_ignore_ascii_case("off");
+    match key {
+        "time" if value.is_empty() => return Err("Use /config time {emoji do time}.".to_string()),
+        "time" if reset => settings.team = DEFAULT_TEAM.to_string(),
+        "time" => settings.team = value.to_string(),
+        "persona" if value.is_empty() || reset => settings.persona = None,
+        "persona" => {
+            let persona = read_personas()?.into_iter().find(|p| p.id.eq_ignore_ascii_case(value)).ok_or_else(|| format!("Persona '{}' não encontrada.", value))?;
+            settings.persona = Some(persona.id);
+        }
+        "idioma" if value.is_empty() || reset => settings.language = None,
+        "idioma" => settings.language = Some(value.to_string()),
+        "desativar" | "ativar" => {
+            let command = format!("/{}", value.trim_start_matches('/').to_lowercase());
+            if command == "/" {
+                return Err(format!("Use /config {} /comando.", key));
+            }
+            if key == "desativar" && ALWAYS_ENABLED.contains(&command.as_str()) {
+                return Err(format!("{} não pode ser desativado.", command));
+            }
+            settings.disabled_commands.retain(|c| *c != command);
+            if key == "desativar" {
+                settings.disabled_commands.push(command);
+            }
+        }
+        "resumo" if value.is_empty() => return Err("Use /config resumo {HH:MM} ou /config resumo off.".to_string()),
+        "resumo" if off => settings.digest_time = None,
+        "resumo" => {
+            let time = NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("Horário inválido: {} (use HH:MM).", value))?;
+            settings.digest_time = Some(time.format("%H:%M").to_string());
+        }
+        "resumosemanal" if value.is_empty() => return Err("Use /config resumosemanal {dia da semana} ou /config resumosemanal off.".to_string()),
+        "resumosemanal" if off => settings.weekly_digest = None,
+        "resumosemanal" => {
+            parse_weekday(value).ok_or_else(|| format!("Dia da semana inválido: {}.", value))?;
+            settings.weekly_digest = Some(value.to_lowercase());
+        }
+        _ => return Err("Use /config [time | persona | idioma | desativar | ativar | resumo | resumosemanal] {valor}.".to_string()),
+    }
+    let updated = settings.clone();
+    write_chat_settings(&all)?;
+    Ok(updated)
+}
+
+/// Every chat with settings or its own data directory.
+pub fn known_chats() -> Vec<i64> {
+    let mut chats: Vec<i64> = read_chat_settings().unwrap_or_default().keys().filter_map(|id| id.parse().ok()).collect();
+    if let Ok(entries) = fs::read_dir(&config().files.chats_dir) {
+        chats.extend(entries.flatten().filter_map(|entry| entry.file_name().to_str()?.parse::<i64>().ok()));
+    }
+    chats.sort();
+    chats.dedup();
+    chats
+}
+
+/// Reads a chat's own copy of `file`. Shared files such as the calendar fall back
+/// to the copy in `DATA_DIR`; `None` means neither exists.
+pub fn read_chat_file(chat_id: i64, file: &Path, shared: bool) -> Result<Option<String>, String> {
+    for path in [Some(config().chat_path(chat_id, file)), shared.then(|| file.to_path_buf())].into_iter().flatten() {
+        match fs::read_to_string(&path) {
+            Ok(data) => return Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+    Ok(None)
+}
+
+pub fn write_chat_file(chat_id: i64, file: &Path, data: &str) -> Result<(), String> {
+    let path = config().chat_path(chat_id, file);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
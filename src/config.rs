@@ -0,0 +1,290 @@
+use crate::context::{read_summary, write_summary, ChatSummary};
+use crate::{read_claims, update_crew, write_claims, Claim, Crew, TEAMS};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the optional file in `DATA_DIR` that overrides the paths below.
+const CONFIG_FILE: &str = "config.json";
+/// Appended to a single-group file once a chat has adopted it, e.g. `claims.json.adotado`.
+const ADOPTED_EXTENSION: &str = "json.adotado";
+
+/// Where every data file lives. Relative paths are resolved against `DATA_DIR`.
+///
+/// Claims, crew, chat logs, summary and digest state belong to a single chat and live in
+/// `chats_dir/{chat_id}/` under the same file name; the calendar and missions
+/// there override the shared copies listed here.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DataFiles {
+    pub papeis: PathBuf,
+    pub claims: PathBuf,
+    pub calendario: PathBuf,
+    pub missoes: PathBuf,
+    pub tripulantes: PathBuf,
+    /// Directory holding one `{time}.json` scoreboard per team.
+    pub times: PathBuf,
+    pub tickets: PathBuf,
+    pub receitas: PathBuf,
+    pub pecas: PathBuf,
+    pub inventarios: PathBuf,
+    pub inventarios_pendentes: PathBuf,
+    pub jogadores: PathBuf,
+    pub nomes_historico: PathBuf,
+    pub conversas: PathBuf,
+    pub resumo_chat: PathBuf,
+    /// When a chat last got its scheduled digests.
+    pub resumo_diario: PathBuf,
+    pub personas: PathBuf,
+    /// Per-chat settings, keyed by chat id.
+    pub chats: PathBuf,
+    pub chats_dir: PathBuf,
+    pub uso: PathBuf,
+    /// Directory of daily `YYYY-MM-DD.jsonl` chat logs, inside each chat's directory.
+    pub chat_logs: PathBuf,
+}
+
+impl Default for DataFiles {
+    fn default() -> Self {
+        DataFiles {
+            papeis: "papeis.json".into(),
+            claims: "claims.json".into(),
+            calendario: "calendario.json".into(),
+            missoes: "missoes.json".into(),
+            tripulantes: "tripulantes.json".into(),
+            times: ".".into(),
+            tickets: "tickets.json".into(),
+            receitas: "receitas.json".into(),
+            pecas: "pecas.json".into(),
+            inventarios: "inventarios.json".into(),
+            inventarios_pendentes: "inventarios_pendentes.json".into(),
+            jogadores: "jogadores.json".into(),
+            nomes_historico: "nomes_historico.json".into(),
+            conversas: "conversas.json".into(),
+            resumo_chat: "resumo_chat.json".into(),
+            resumo_diario: "resumo_diario.json".into(),
+            personas: "personas.json".into(),
+            chats: "chats.json".into(),
+            chats_dir: "chats".into(),
+            uso: "uso.json".into(),
+            chat_logs: "log".into(),
+        }
+    }
+}
+
+impl DataFiles {
+    /// The scoreboard file of a team. Only names listed in `TEAMS` are accepted, so
+    /// user text can never point the path outside the teams directory.
+    pub fn team(&self, team_name: &str) -> Result<PathBuf, String> {
+        let team_name = TEAMS.iter().find(|t| **t == team_name).ok_or_else(|| format!("Time desconhecido: {}", team_name))?;
+        Ok(self.times.join(format!("{}.json", team_name)))
+    }
+
+    /// Every file the bot reads, with the content `--init` writes for it.
+    fn scaffold(&self) -> Vec<(PathBuf, &'static str)> {
+        let mut files = vec![
+            (self.papeis.clone(), "[]"),
+            (self.calendario.clone(), "[]"),
+            (self.missoes.clone(), r#"{ "title": "", "text": "" }"#),
+            (self.tickets.clone(), "{}"),
+            (self.receitas.clone(), "{}"),
+            (self.pecas.clone(), "{}"),
+            (self.inventarios.clone(), "{}"),
+            (self.inventarios_pendentes.clone(), "{}"),
+            (self.jogadores.clone(), "{}"),
+            (self.nomes_historico.clone(), "[]"),
+            (self.conversas.clone(), "{}"),
+            (self.personas.clone(), include_str!("../personas.json")),
+            (self.chats.clone(), "{}"),
+            (self.uso.clone(), r#"{ "date": "", "total": { "requests": 0, "prompt_tokens": 0, "output_tokens": 0 } }"#),
+        ];
+        files.extend(TEAMS.iter().map(|team_name| (self.times.join(format!("{}.json", team_name)), "[]")));
+        files
+    }
+
+    fn resolve(&mut self, data_dir: &Path) {
+        for path in [
+            &mut self.papeis,
+            &mut self.claims,
+            &mut self.calendario,
+            &mut self.missoes,
+            &mut self.tripulantes,
+            &mut self.times,
+            &mut self.tickets,
+            &mut self.receitas,
+            &mut self.pecas,
+            &mut self.inventarios,
+            &mut self.inventarios_pendentes,
+            &mut self.jogadores,
+            &mut self.nomes_historico,
+            &mut self.conversas,
+            &mut self.resumo_chat,
+            &mut self.resumo_diario,
+            &mut self.personas,
+            &mut self.chats,
+            &mut self.chats_dir,
+            &mut self.uso,
+            &mut self.chat_logs,
+        ] {
+            *path = data_dir.join(&*path);
+        }
+    }
+}
+
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub files: DataFiles,
+}
+
+impl Config {
+    /// A chat's own copy of `file`, e.g. `chats/-100123/claims.json`.
+    pub fn chat_path(&self, chat_id: i64, file: &Path) -> PathBuf {
+        let name = file.file_name().unwrap_or(file.as_os_str());
+        self.files.chats_dir.join(chat_id.to_string()).join(name)
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Reads `DATA_DIR` (the working directory by default) and its optional config.json.
+pub fn init() -> Result<&'static Config, String> {
+    let data_dir = PathBuf::from(env::var("DATA_DIR").unwrap_or_else(|_| ".".to_string()));
+    let config_path = data_dir.join(CONFIG_FILE);
+    let mut files = match fs::read_to_string(&config_path) {
+        Ok(data) => serde_json::from_str(&data).map_err(|e| format!("{}: {}", config_path.display(), e))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => DataFiles::default(),
+        Err(err) => return Err(format!("{}: {}", config_path.display(), err)),
+    };
+    files.resolve(&data_dir);
+    Ok(CONFIG.get_or_init(|| Config { data_dir, files }))
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("config::init runs before anything reads data files")
+}
+
+/// Required files that do not exist yet.
+pub fn missing_files() -> Vec<PathBuf> {
+    config().files.scaffold().into_iter().map(|(path, _)| path).filter(|path| !path.exists()).collect()
+}
+
+/// Creates the data directory with empty versions of every missing file and a
+/// config.json listing the default paths. Existing files are left untouched.
+pub fn scaffold_data_dir() -> Result<Vec<PathBuf>, String> {
+    let config = config();
+    let mut created = Vec::new();
+    fs::create_dir_all(&config.data_dir).map_err(|e| format!("{}: {}", config.data_dir.display(), e))?;
+
+    let config_path = config.data_dir.join(CONFIG_FILE);
+    if !config_path.exists() {
+        let data = serde_json::to_string_pretty(&DataFiles::default()).map_err(|e| e.to_string())?;
+        fs::write(&config_path, data).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+        created.push(config_path);
+    }
+    for (path, content) in config.files.scaffold() {
+        if path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        created.push(path);
+    }
+    Ok(created)
+}
+
+/// The single-group claims, crew and summary left in `DATA_DIR` by deployments from
+/// before chats were namespaced. No chat reads them until `--adotar` merges them in.
+pub fn unadopted_legacy_files() -> Vec<PathBuf> {
+    let files = &config().files;
+    [&files.claims, &files.tripulantes, &files.resumo_chat].into_iter().filter(|file| file.exists()).cloned().collect()
+}
+
+fn read_legacy<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Moves an adopted file aside so startup stops asking for it, keeping it as a backup.
+fn retire(path: &Path) -> Result<(), String> {
+    fs::rename(path, path.with_extension(ADOPTED_EXTENSION)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Merges the single-group files into a chat's own directory, even when the chat already
+/// wrote its own copies since the upgrade, then renames them to `*.json.adotado`.
+pub fn adopt_legacy_files(chat_id: i64) -> Result<Vec<PathBuf>, String> {
+    let files = &config().files;
+    let mut adopted = Vec::new();
+    if files.tripulantes.exists() {
+        let legacy = read_legacy::<Crew>(&files.tripulantes)?;
+        update_crew(chat_id, |crew| {
+            crew.adopt(legacy);
+            Ok(())
+        })?;
+        retire(&files.tripulantes)?;
+        adopted.push(config().chat_path(chat_id, &files.tripulantes));
+    }
+    if files.claims.exists() {
+        // Claims made in the chat since the upgrade are newer than the legacy ones.
+        let mut claims = read_claims(chat_id)?;
+        for (key, claim) in read_legacy::<HashMap<String, Claim>>(&files.claims)? {
+            claims.entry(key).or_insert(claim);
+        }
+        write_claims(chat_id, &claims)?;
+        retire(&files.claims)?;
+        adopted.push(config().chat_path(chat_id, &files.claims));
+    }
+    if files.resumo_chat.exists() {
+        if read_summary(chat_id)?.summary.is_empty() {
+            write_summary(chat_id, &read_legacy::<ChatSummary>(&files.resumo_chat)?)?;
+        }
+        retire(&files.resumo_chat)?;
+        adopted.push(config().chat_path(chat_id, &files.resumo_chat));
+    }
+    Ok(adopted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_teams_resolve_inside_the_teams_directory() {
+        let files = DataFiles { times: PathBuf::from("dados/times"), ..Default::default() };
+        for team_name in TEAMS {
+            assert_eq!(files.team(team_name).unwrap(), PathBuf::from(format!("dados/times/{}.json", team_name)));
+        }
+    }
+
+    #[test]
+    fn malicious_team_names_are_rejected() {
+        let files = DataFiles::default();
+        for name in [
+            "",
+            ".",
+            "..",
+            "../jogadores",
+            "../../etc/passwd",
+            "/etc/passwd",
+            "will/../jogadores",
+            "will/../../segredo",
+            "..\\jogadores",
+            "will.json",
+            "will\0",
+            "will ",
+            " will",
+            "WILL",
+            "jogadores",
+            "tripulantes",
+            "will\njack",
+            "%2e%2e/jogadores",
+        ] {
+            assert!(files.team(name).is_err(), "{:?} should not be a team", name);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::chatlog::{read_log, LogEntry, REDACTED_TEXT};
+use crate::chats::{read_chat_file, write_chat_file};
+use crate::config::config;
+use crate::memory::estimate_tokens;
+use crate::quota::TokenUsage;
+use crate::{read_calendar, read_crew, read_missions, read_scoreboard, TEAMS};
+use chrono::DateTime;
+use gemini_rust::Gemini;
+use serde::{Deserialize, Serialize};
+
+/// Most recent chat lines considered for every question.
+const CHAT_CONTEXT_LINES: usize = 60;
+/// Budget for those lines; older ones are dropped first.
+const CHAT_TOKEN_BUDGET: usize = 1500;
+/// Older lines are only folded into the summary once this many have piled up.
+const SUMMARY_BATCH_LINES: usize = 200;
+
+const DATE_KEYWORDS: &[&str] = &[
+    "jogo", "partida", "quando", "dia", "data", "hora", "horario", "calendario", "proximo", "hoje", "amanha",
+    "semana", "segunda", "terca", "quarta", "quinta", "sexta", "sabado", "domingo", "fase",
+];
+const SCORE_KEYWORDS: &[&str] = &[
+    "ponto", "pontuacao", "ranking", "placar", "primeiro", "ultimo", "lider", "ganhando", "vencendo", "missao",
+    "missoes", "barbossa", "jack", "elizabeth",
+];
+const CREW_KEYWORDS: &[&str] = &["tripulacao", "tripulante", "capitao", "lider", "membro", "subs", "quem"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChatSummary {
+    /// Timestamp of the newest log entry folded into the summary.
+    #[serde(default)]
+    pub covered_until: String,
+    pub summary: String,
+}
+
+pub fn read_summary(chat_id: i64) -> Result<ChatSummary, String> {
+    let Some(data) = read_chat_file(chat_id, &config().files.resumo_chat, false)? else {
+        return Ok(ChatSummary::default());
+    };
+    let summary: ChatSummary = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(summary)
+}
+
+pub fn write_summary(chat_id: i64, summary: &ChatSummary) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(summary).map_err(|e| e.to_string())?;
+    write_chat_file(chat_id, &config().files.resumo_chat, &data)
+}
+
+/// Forgets a chat's running summary; the next question rebuilds it from the log as it is now.
+pub fn reset_summary(chat_id: i64) -> Result<(), String> {
+    if read_summary(chat_id)?.summary.is_empty() {
+        return Ok(());
+    }
+    write_summary(chat_id, &ChatSummary::default())
+}
+
+/// Lowercases and strips Portuguese accents so keyword checks ignore them.
+pub fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            _ => c,
+        })
+        .collect()
+}
+
+fn mentions(question: &str, keywords: &[&str]) -> bool {
+    question
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| keywords.iter().any(|k| word.starts_with(k)))
+}
+
+/// Takes the newest chat lines that fit both the line and token limits.
+fn recent_chat_lines(lines: &[String]) -> usize {
+    let mut tokens = 0;
+    let mut taken = 0;
+    for line in lines.iter().rev().take(CHAT_CONTEXT_LINES) {
+        tokens += estimate_tokens(line);
+        if tokens > CHAT_TOKEN_BUDGET {
+            break;
+        }
+        taken += 1;
+    }
+    taken
+}
+
+/// Folds chat entries that fell out of the recent window into a running summary.
+async fn summarise_older(gemini: &Gemini, chat_id: i64, older: &[LogEntry], usage: &mut TokenUsage) -> String {
+    let mut summary = read_summary(chat_id).unwrap_or_default();
+    let covered_until = DateTime::parse_from_rfc3339(&summary.covered_until).ok();
+    let pending: Vec<String> = older
+        .iter()
+        .filter(|entry| match (covered_until, entry.time()) {
+            (Some(covered), Some(time)) => time > covered,
+            _ => true,
+        })
+        .map(LogEntry::render)
+        .collect();
+    if pending.len() < SUMMARY_BATCH_LINES {
+        return summary.summary;
+    }
+
+    let prompt = format!(
+        "Resumo anterior:\n{}\n\nNovas mensagens:\n{}\n\nAtualize o resumo do chat em até 10 tópicos curtos, em Português do Brasil, mantendo decisões, jogos combinados e resultados.",
+        summary.summary,
+        pending.join("\n")
+    );
+    match gemini.generate_content().with_user_message(prompt).execute().await {
+        Ok(response) => {
+            if let Some(metadata) = &response.usage_metadata {
+                usage.add(metadata);
+            }
+            let covered_until = older.last().map(|entry| entry.timestamp.clone()).unwrap_or_default();
+            summary = ChatSummary { covered_until, summary: response.text() };
+            if let Err(err) = write_summary(chat_id, &summary) {
+                println!("Failed to write chat summary: {}", err);
+            }
+        }
+        Err(err) => println!("Failed to summarise chat: {}", err),
+    }
+    summary.summary
+}
+
+/// A named block of context that persona templates can place with `{key}`.
+pub struct ContextSection {
+    pub key: &'static str,
+    pub title: &'static str,
+    pub body: String,
+}
+
+/// Builds the prompt context from only the data sources the question needs.
+pub async fn build_context(gemini: &Gemini, chat_id: i64, question: &str, usage: &mut TokenUsage) -> Vec<ContextSection> {
+    let question = fold(question);
+    let mut sections = Vec::new();
+
+    if mentions(&question, DATE_KEYWORDS) {
+        if let Ok(games) = read_calendar(chat_id) {
+            let mut calendar = String::new();
+            for game in games {
+                calendar.push_str(&format!("{} - {} às {} ({}) - {}\n", game.date, game.day_of_week, game.time, game.phase, game.teams.join(" vs ")));
+            }
+            sections.push(ContextSection { key: "calendario", title: "Contexto do Calendário", body: calendar });
+        }
+    }
+
+    if mentions(&question, SCORE_KEYWORDS) {
+        if let Ok(mission) = read_missions(chat_id) {
+            sections.push(ContextSection { key: "missoes", title: "Contexto das Missões", body: format!("{}\n\n{}", mission.title, mission.text) });
+        }
+        let mut scores = String::new();
+        for team_name in TEAMS {
+            if let Ok(players) = read_scoreboard(team_name) {
+                scores.push_str(&format!("Time {}:\n", team_name.to_uppercase()));
+                for (i, player) in players.iter().enumerate() {
+                    scores.push_str(&format!("{}. {} ({}): {} pontos\n", i + 1, player.name, player.user, player.points));
+                }
+            }
+        }
+        sections.push(ContextSection { key: "pontuacao", title: "Contexto da Pontuação", body: scores });
+    }
+
+    if mentions(&question, CREW_KEYWORDS) {
+        if let Ok(crew) = read_crew(chat_id) {
+            let crew_context = serde_json::to_string(&crew).unwrap_or_default();
+            sections.push(ContextSection { key: "tripulacao", title: "Contexto da Tripulação", body: crew_context });
+        }
+    }
+
+    // Opted-out users stay out of the prompt altogether.
+    let entries: Vec<LogEntry> = read_log(chat_id).into_iter().filter(|entry| entry.text != REDACTED_TEXT).collect();
+    let lines: Vec<String> = entries.iter().map(LogEntry::render).collect();
+    let recent = recent_chat_lines(&lines);
+    let (older, _) = entries.split_at(entries.len() - recent);
+    let recent_lines = &lines[lines.len() - recent..];
+    let summary = summarise_older(gemini, chat_id, older, usage).await;
+    if !summary.is_empty() {
+        sections.push(ContextSection { key: "resumo", title: "Resumo das Conversas Anteriores", body: summary });
+    }
+    sections.push(ContextSection { key: "chat", title: "Contexto do Chat", body: recent_lines.join("\n") });
+
+    sections
+}
@@ -0,0 +1,293 @@
+use crate::chatlog::{read_log, LogEntry, REDACTED_TEXT};
+use crate::chats::{known_chats, read_chat_file, settings_for_chat, write_chat_file, ChatSettings};
+use crate::config::config;
+use crate::context::{fold, ContextSection};
+use crate::gemini::{check_finish, with_retry};
+use crate::memory::estimate_tokens;
+use crate::persona::{persona_for_chat, render_system_prompt};
+use crate::quota::{daily_budget_exceeded, record_usage, TokenUsage};
+use crate::streaming::truncate;
+use crate::{find_next_game, read_calendar};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
+use frankenstein::client_reqwest::Bot;
+use frankenstein::methods::SendMessageParams;
+use frankenstein::AsyncTelegramApi;
+use gemini_rust::{Gemini, Message as GeminiMessage};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// How often the scheduler looks for chats whose digest is due.
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Weekly digests go out at this time in chats without a daily digest time.
+const DEFAULT_DIGEST_TIME: &str = "21:00";
+/// Longer periods are summarised in chunks of about this many tokens first.
+const DIGEST_CHUNK_TOKENS: usize = 6000;
+/// Usage of scheduled digests is recorded under this user.
+const DIGEST_USER_ID: u64 = 0;
+
+/// When the last digests were posted and how far they read, kept per chat in resumo_diario.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DigestState {
+    #[serde(default)]
+    pub last_daily: String,
+    #[serde(default)]
+    pub last_weekly: String,
+    /// Timestamp of the newest log entry in the last daily digest.
+    #[serde(default)]
+    pub daily_covered_until: String,
+    /// Same for the weekly digest.
+    #[serde(default)]
+    pub weekly_covered_until: String,
+}
+
+pub fn read_digest_state(chat_id: i64) -> Result<DigestState, String> {
+    let Some(data) = read_chat_file(chat_id, &config().files.resumo_diario, false)? else {
+        return Ok(DigestState::default());
+    };
+    let state: DigestState = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(state)
+}
+
+fn write_digest_state(chat_id: i64, state: &DigestState) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    write_chat_file(chat_id, &config().files.resumo_diario, &data)
+}
+
+/// Accepts Portuguese weekday names with or without accents and the `-feira` suffix.
+pub fn parse_weekday(name: &str) -> Option<Weekday> {
+    let name = fold(name.trim());
+    match name.strip_suffix("-feira").unwrap_or(&name) {
+        "domingo" => Some(Weekday::Sun),
+        "segunda" => Some(Weekday::Mon),
+        "terca" => Some(Weekday::Tue),
+        "quarta" => Some(Weekday::Wed),
+        "quinta" => Some(Weekday::Thu),
+        "sexta" => Some(Weekday::Fri),
+        "sabado" => Some(Weekday::Sat),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    fn days(self) -> i64 {
+        match self {
+            Period::Daily => 1,
+            Period::Weekly => 7,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Period::Daily => "do dia",
+            Period::Weekly => "da semana",
+        }
+    }
+}
+
+/// The digests a chat is owed right now, given its settings and what was already posted today.
+fn due_periods(settings: &ChatSettings, state: &DigestState, now: DateTime<Local>) -> Vec<Period> {
+    let today = now.date_naive().to_string();
+    let is_due = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").is_ok_and(|time| now.time() >= time);
+    let mut due = Vec::new();
+    if let Some(time) = &settings.digest_time {
+        if is_due(time) && state.last_daily != today {
+            due.push(Period::Daily);
+        }
+    }
+    if let Some(day) = settings.weekly_digest.as_deref().and_then(parse_weekday) {
+        let time = settings.digest_time.as_deref().unwrap_or(DEFAULT_DIGEST_TIME);
+        if now.weekday() == day && is_due(time) && state.last_weekly != today {
+            due.push(Period::Weekly);
+        }
+    }
+    due
+}
+
+/// Splits the rendered lines into chunks that each fit the chunk budget.
+fn chunk_lines(lines: &[String]) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut tokens = 0;
+    for line in lines {
+        let line_tokens = estimate_tokens(line);
+        if tokens + line_tokens > DIGEST_CHUNK_TOKENS && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            tokens = 0;
+        }
+        chunk.push_str(line);
+        chunk.push('\n');
+        tokens += line_tokens;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Turns one chunk of a long period into a plain list of facts for the final digest.
+async fn summarise_chunk(gemini: &Gemini, chunk: &str, usage: &mut TokenUsage) -> Result<String, String> {
+    let prompt = format!(
+        "Mensagens do chat:\n{}\n\nResuma estas mensagens em até 10 tópicos curtos, em Português do Brasil, mantendo decisões, jogos combinados, quem vai jogar e resultados.",
+        chunk
+    );
+    let response = with_retry(|| gemini.generate_content().with_user_message(prompt.clone()).execute()).await.map_err(|e| e.to_string())?;
+    check_finish(&response).map_err(|e| e.to_string())?;
+    if let Some(metadata) = &response.usage_metadata {
+        usage.add(metadata);
+    }
+    Ok(response.text())
+}
+
+/// The entries a digest has not covered yet: everything after the previous digest of the
+/// same period, or the period's days when there was none. Messages written after one
+/// digest was posted are picked up by the next one.
+fn pending_entries(chat_id: i64, period: Period, covered_until: &str) -> Vec<LogEntry> {
+    let covered_until = DateTime::parse_from_rfc3339(covered_until).ok();
+    let first_day = Local::now().date_naive() - Duration::days(period.days() - 1);
+    read_log(chat_id)
+        .into_iter()
+        .filter(|entry| match (covered_until, entry.time()) {
+            (Some(covered), Some(time)) => time > covered,
+            (None, Some(time)) => time.date_naive() >= first_day,
+            (_, None) => false,
+        })
+        .collect()
+}
+
+/// Writes the digest of the given entries in the chat's persona, or `None` when nobody
+/// but opted-out users wrote anything.
+async fn generate_digest(chat_id: i64, settings: &ChatSettings, period: Period, entries: &[LogEntry], usage: &mut TokenUsage) -> Result<Option<String>, String> {
+    let today = Local::now().date_naive();
+    // Opted-out users and bot commands stay out of the digest.
+    let lines: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.text != REDACTED_TEXT && !entry.text.starts_with('/'))
+        .map(LogEntry::render)
+        .collect();
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    let first_day = entries.first().and_then(LogEntry::time).map_or(today, |time| time.date_naive());
+
+    let gemini_api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not set");
+    let gemini = Gemini::new(gemini_api_key);
+    let chunks = chunk_lines(&lines);
+    let (key, title, body) = if chunks.len() == 1 {
+        ("chat", "Contexto do Chat", chunks.concat())
+    } else {
+        let mut partials = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            partials.push(format!("Parte {}:\n{}", i + 1, summarise_chunk(&gemini, chunk, usage).await?));
+        }
+        ("resumo", "Resumo das Conversas Anteriores", partials.join("\n\n"))
+    };
+    let mut sections = vec![ContextSection { key, title, body }];
+    if let Some(game) = read_calendar(chat_id).ok().and_then(|games| find_next_game(games, &settings.team, today)) {
+        let body = format!("{} - {} às {} ({}) - {}", game.date, game.day_of_week, game.time, game.phase, game.teams.join(" vs "));
+        sections.push(ContextSection { key: "calendario", title: "Próximo Jogo do Time", body });
+    }
+
+    let persona = persona_for_chat(chat_id)?;
+    let system_prompt = render_system_prompt(&persona, &sections);
+    let request = format!(
+        "Escreva para a tripulação o resumo {} do chat ({} a {}): as decisões tomadas, quem vai jogar o próximo jogo e os resultados comentados. Seja breve e use tópicos.",
+        period.label(),
+        first_day.format("%d/%m"),
+        today.format("%d/%m")
+    );
+    let response = with_retry(|| {
+        gemini
+            .generate_content()
+            .with_system_prompt(system_prompt.clone())
+            .with_messages(vec![GeminiMessage::user(request.clone())])
+            .execute()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    check_finish(&response).map_err(|e| e.to_string())?;
+    if let Some(metadata) = &response.usage_metadata {
+        usage.add(metadata);
+    }
+    let text = response.text();
+    if text.trim().is_empty() {
+        return Err("empty digest".to_string());
+    }
+    Ok(Some(text))
+}
+
+/// Posts every digest that is due in every chat.
+async fn post_due_digests(bot: &Bot) {
+    let now = Local::now();
+    for chat_id in known_chats() {
+        let Ok(settings) = settings_for_chat(chat_id) else { continue };
+        let mut state = match read_digest_state(chat_id) {
+            Ok(state) => state,
+            Err(err) => {
+                println!("Failed to read digest state of chat {}: {}", chat_id, err);
+                continue;
+            }
+        };
+        for period in due_periods(&settings, &state, now) {
+            // Retried on the next check, once the budget resets.
+            if daily_budget_exceeded() {
+                return;
+            }
+            let covered_until = match period {
+                Period::Daily => &state.daily_covered_until,
+                Period::Weekly => &state.weekly_covered_until,
+            };
+            let entries = pending_entries(chat_id, period, covered_until);
+            let mut usage = TokenUsage { requests: 1, ..Default::default() };
+            let digest = generate_digest(chat_id, &settings, period, &entries, &mut usage).await;
+            if let Err(err) = record_usage(chat_id, DIGEST_USER_ID, "Resumo automático", &usage) {
+                println!("Failed to record usage: {}", err);
+            }
+            // A failed digest leaves its messages to the next one.
+            if let (Ok(_), Some(last)) = (&digest, entries.last()) {
+                match period {
+                    Period::Daily => state.daily_covered_until = last.timestamp.clone(),
+                    Period::Weekly => state.weekly_covered_until = last.timestamp.clone(),
+                }
+            }
+            match digest {
+                Ok(Some(text)) => {
+                    let send_message_params = SendMessageParams::builder()
+                        .chat_id(chat_id)
+                        .text(truncate(&text))
+                        .build();
+                    if let Err(err) = bot.send_message(&send_message_params).await {
+                        println!("Failed to send message: {:?}", err);
+                    }
+                }
+                Ok(None) => println!("Skipping the {:?} digest of chat {}: no messages", period, chat_id),
+                // Not retried today, so a failing model does not get called every minute.
+                Err(err) => println!("Failed to generate the {:?} digest of chat {}: {}", period, chat_id, err),
+            }
+            let today = now.date_naive().to_string();
+            match period {
+                Period::Daily => state.last_daily = today,
+                Period::Weekly => state.last_weekly = today,
+            }
+            if let Err(err) = write_digest_state(chat_id, &state) {
+                println!("Failed to write digest state of chat {}: {}", chat_id, err);
+            }
+        }
+    }
+}
+
+/// Starts the background job posting the daily and weekly digests chats asked for
+/// with `/config resumo` and `/config resumosemanal`.
+pub fn spawn_scheduler(bot: Bot) {
+    tokio::spawn(async move {
+        loop {
+            post_due_digests(&bot).await;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,131 @@
+use futures_util::{Stream, StreamExt};
+use gemini_rust::{Error, GenerationResponse};
+use std::fmt;
+use std::future::Future;
+use tokio::time::{sleep, timeout, Duration};
+
+/// Attempts per request before giving up on transient failures.
+const MAX_ATTEMPTS: u32 = 3;
+/// Base delay between attempts; doubled after each failure.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Limit for a single attempt; for streams this only covers opening the stream.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest wait for the next chunk once a stream is open, since the HTTP client has no timeout.
+const STREAM_CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Finish reasons meaning the answer was withheld by the model's filters.
+const BLOCKED_FINISH_REASONS: &[&str] = &["SAFETY", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII", "RECITATION", "IMAGE_SAFETY"];
+
+#[derive(Debug)]
+pub enum AskError {
+    Blocked(String),
+    Empty,
+    Invalid(String),
+    Timeout,
+    Network(String),
+    Api(u16, String),
+    ToolLoop,
+}
+
+impl AskError {
+    /// Short code shown to the chat so admins can match it against the logs.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AskError::Blocked(_) => "W-BLQ",
+            AskError::Empty => "W-VAZ",
+            AskError::Invalid(_) => "W-INV",
+            AskError::Timeout => "W-TMP",
+            AskError::Network(_) => "W-REDE",
+            AskError::Api(429, _) => "W-429",
+            AskError::Api(_, _) => "W-API",
+            AskError::ToolLoop => "W-FER",
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            AskError::Timeout | AskError::Network(_) => true,
+            AskError::Api(status, _) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::Blocked(reason) => write!(f, "response blocked: {}", reason),
+            AskError::Empty => write!(f, "empty response"),
+            AskError::Invalid(err) => write!(f, "invalid response: {}", err),
+            AskError::Timeout => write!(f, "request timed out"),
+            AskError::Network(err) => write!(f, "network error: {}", err),
+            AskError::Api(status, message) => write!(f, "API error {}: {}", status, message),
+            AskError::ToolLoop => write!(f, "too many tool calls"),
+        }
+    }
+}
+
+impl From<Error> for AskError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::HttpError(e) if e.is_timeout() => AskError::Timeout,
+            Error::HttpError(e) => AskError::Network(e.to_string()),
+            Error::ApiError { status_code, message } => AskError::Api(status_code, message),
+            // A blocked prompt comes back without `candidates`, which fails to parse.
+            Error::JsonError(e) => AskError::Invalid(e.to_string()),
+            other => AskError::Invalid(other.to_string()),
+        }
+    }
+}
+
+/// Runs a request with a per-attempt timeout, retrying transient failures with backoff.
+pub async fn with_retry<T, F, Fut>(mut request: F) -> Result<T, AskError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = gemini_rust::Result<T>>,
+{
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        let err = match timeout(REQUEST_TIMEOUT, request()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(err)) => AskError::from(err),
+            Err(_) => AskError::Timeout,
+        };
+        if attempt >= MAX_ATTEMPTS || !err.is_transient() {
+            return Err(err);
+        }
+        println!("Gemini attempt {} failed ({}), retrying in {:?}", attempt, err, delay);
+        sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Reads the next chunk of a streamed answer, failing with `AskError::Timeout` when the stream stalls.
+pub async fn next_chunk<S>(stream: &mut S) -> Result<Option<GenerationResponse>, AskError>
+where
+    S: Stream<Item = gemini_rust::Result<GenerationResponse>> + Unpin,
+{
+    match timeout(STREAM_CHUNK_TIMEOUT, stream.next()).await {
+        Ok(Some(chunk)) => Ok(Some(chunk?)),
+        Ok(None) => Ok(None),
+        Err(_) => Err(AskError::Timeout),
+    }
+}
+
+/// Fails on blocked prompts or answers; returns whether the answer hit the token limit.
+pub fn check_finish(response: &GenerationResponse) -> Result<bool, AskError> {
+    if let Some(reason) = response.prompt_feedback.as_ref().and_then(|f| f.block_reason.clone()) {
+        return Err(AskError::Blocked(reason));
+    }
+    let mut truncated = false;
+    for candidate in &response.candidates {
+        match candidate.finish_reason.as_deref() {
+            Some(reason) if BLOCKED_FINISH_REASONS.contains(&reason) => return Err(AskError::Blocked(reason.to_string())),
+            Some("MAX_TOKENS") => truncated = true,
+            _ => {}
+        }
+    }
+    Ok(truncated)
+}
@@ -0,0 +1,575 @@
+use crate::config::config;
+use crate::context::fold;
+use crate::gemini::{with_retry, AskError};
+use crate::media::Image;
+use crate::players::{read_players, Target};
+use crate::read_crew;
+use crate::quota::TokenUsage;
+use chrono::Local;
+use gemini_rust::{Content, Gemini, Message as GeminiMessage, Part, Role};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemCategory {
+    Ticket,
+    VipTicket,
+    Recipe,
+    Piece,
+    Selected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InventoryItem {
+    pub emoji: String,
+    pub count: u32,
+    pub category: ItemCategory,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerInventory {
+    pub first_name: String,
+    pub username: String,
+    pub updated_at: String,
+    #[serde(default)]
+    pub items: Vec<InventoryItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TicketItem {
+    pub emoji: String,
+    pub count: u32,
+    #[serde(default)]
+    pub vip: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Tickets {
+    pub items: Vec<TicketItem>,
+    pub selected_ticket: Option<String>,
+}
+
+impl Tickets {
+    /// Renders the same layout the Werewolf bot uses for `/tickets`.
+    pub fn render(&self) -> String {
+        let regular: Vec<&TicketItem> = self.items.iter().filter(|t| !t.vip).collect();
+        let vip: Vec<&TicketItem> = self.items.iter().filter(|t| t.vip).collect();
+        format!(
+            "🎟 Tickets: \n\n{}\n\n🎫 VIP Tickets: \n\n{}\n\n✅ Selected ticket:\n{}",
+            render_counts(regular.iter().map(|t| (t.emoji.as_str(), t.count))),
+            render_counts(vip.iter().map(|t| (t.emoji.as_str(), t.count))),
+            self.selected_ticket.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// The hand-written format: three display strings per player.
+#[derive(Deserialize)]
+struct LegacyTickets {
+    tickets: String,
+    vip_tickets: String,
+    selected_ticket: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTickets {
+    Typed(Tickets),
+    Legacy(LegacyTickets),
+}
+
+impl From<StoredTickets> for Tickets {
+    fn from(stored: StoredTickets) -> Self {
+        match stored {
+            StoredTickets::Typed(tickets) => tickets,
+            StoredTickets::Legacy(legacy) => {
+                let text = format!("{}\n{}\n{}", legacy.tickets, legacy.vip_tickets, legacy.selected_ticket);
+                tickets_from_items(&parse_inventory_text(&text))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRecipes {
+    Typed(Vec<String>),
+    Legacy(String),
+}
+
+impl From<StoredRecipes> for Vec<String> {
+    fn from(stored: StoredRecipes) -> Self {
+        match stored {
+            StoredRecipes::Typed(recipes) => recipes,
+            StoredRecipes::Legacy(text) => recipes_from_items(&parse_inventory_text(&text)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPieces {
+    Typed(u32),
+    Legacy(String),
+}
+
+impl From<StoredPieces> for u32 {
+    fn from(stored: StoredPieces) -> Self {
+        match stored {
+            StoredPieces::Typed(pieces) => pieces,
+            StoredPieces::Legacy(text) => pieces_from_items(&parse_inventory_text(&text)),
+        }
+    }
+}
+
+pub fn read_tickets() -> Result<HashMap<String, Tickets>, String> {
+    let data = fs::read_to_string(&config().files.tickets).map_err(|e| e.to_string())?;
+    let tickets: HashMap<String, StoredTickets> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(tickets.into_iter().map(|(name, t)| (name, t.into())).collect())
+}
+
+pub fn read_receitas() -> Result<HashMap<String, Vec<String>>, String> {
+    let data = fs::read_to_string(&config().files.receitas).map_err(|e| e.to_string())?;
+    let receitas: HashMap<String, StoredRecipes> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(receitas.into_iter().map(|(name, r)| (name, r.into())).collect())
+}
+
+pub fn read_pecas() -> Result<HashMap<String, u32>, String> {
+    let data = fs::read_to_string(&config().files.pecas).map_err(|e| e.to_string())?;
+    let pecas: HashMap<String, StoredPieces> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(pecas.into_iter().map(|(name, p)| (name, p.into())).collect())
+}
+
+fn tickets_from_items(items: &[InventoryItem]) -> Tickets {
+    let mut tickets = Tickets::default();
+    for item in items {
+        match item.category {
+            ItemCategory::Ticket | ItemCategory::VipTicket => tickets.items.push(TicketItem {
+                emoji: item.emoji.clone(),
+                count: item.count,
+                vip: item.category == ItemCategory::VipTicket,
+            }),
+            ItemCategory::Selected => tickets.selected_ticket = Some(item.emoji.clone()),
+            _ => {}
+        }
+    }
+    tickets
+}
+
+fn recipes_from_items(items: &[InventoryItem]) -> Vec<String> {
+    items.iter().filter(|i| i.category == ItemCategory::Recipe).map(|i| i.emoji.clone()).collect()
+}
+
+fn pieces_from_items(items: &[InventoryItem]) -> u32 {
+    items.iter().filter(|i| i.category == ItemCategory::Piece).map(|i| i.count).sum()
+}
+
+fn render_counts<'a>(counts: impl Iterator<Item = (&'a str, u32)>) -> String {
+    counts
+        .map(|(emoji, count)| if count == 1 { emoji.to_string() } else { format!("{} x{}", emoji, count) })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn render_recipes(recipes: &[String]) -> String {
+    format!("📃 Your recipes: \n\n{}", recipes.join(", "))
+}
+
+pub fn render_pieces(pieces: u32) -> String {
+    format!("🧩 Pieces: {}", pieces)
+}
+
+/// One player's holdings, merged from the old name-keyed files and /inventario.
+pub struct CrewInventory {
+    pub name: String,
+    pub tickets: Tickets,
+    pub recipes: Vec<String>,
+    pub pieces: u32,
+}
+
+impl From<PlayerInventory> for CrewInventory {
+    fn from(scanned: PlayerInventory) -> Self {
+        CrewInventory {
+            tickets: tickets_from_items(&scanned.items),
+            recipes: recipes_from_items(&scanned.items),
+            pieces: pieces_from_items(&scanned.items),
+            name: scanned.first_name,
+        }
+    }
+}
+
+/// Every known inventory with its owner's user id when known; one confirmed through
+/// /inventario replaces the hand-written entry with the same first name.
+fn known_inventories() -> Result<Vec<(Option<u64>, CrewInventory)>, String> {
+    let players = read_players()?;
+    let mut tickets = read_tickets()?;
+    let mut recipes = read_receitas()?;
+    let mut pieces = read_pecas()?;
+
+    let mut names: Vec<String> = tickets.keys().chain(recipes.keys()).chain(pieces.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+    let mut inventories: Vec<(Option<u64>, CrewInventory)> = names
+        .into_iter()
+        .map(|name| {
+            let user_id = players.iter().find(|(_, p)| p.answers_to(&name)).and_then(|(id, _)| id.parse().ok());
+            let inventory = CrewInventory {
+                tickets: tickets.remove(&name).unwrap_or_default(),
+                recipes: recipes.remove(&name).unwrap_or_default(),
+                pieces: pieces.remove(&name).unwrap_or_default(),
+                name,
+            };
+            (user_id, inventory)
+        })
+        .collect();
+
+    for (id, scanned) in read_inventories()? {
+        let entry = (id.parse().ok(), CrewInventory::from(scanned));
+        match inventories.iter_mut().find(|(_, i)| i.name.eq_ignore_ascii_case(&entry.1.name)) {
+            Some(existing) => *existing = entry,
+            None => inventories.push(entry),
+        }
+    }
+    Ok(inventories)
+}
+
+/// The inventories of the members aboard a chat's crew, matched by user id, by the
+/// registry's username for crew entries without one, or by first name as a last resort.
+pub fn crew_inventories(chat_id: i64) -> Result<Vec<CrewInventory>, String> {
+    let crew = read_crew(chat_id)?;
+    let players = read_players()?;
+    let aboard = |user_id: Option<u64>, name: &str| {
+        let username = user_id.and_then(|id| players.get(&id.to_string())).map(|p| p.username.as_str()).unwrap_or_default();
+        crew.all_members().filter(|m| m.is_crewmember).any(|m| match (m.user_id, user_id) {
+            (Some(member_id), Some(user_id)) => member_id == user_id,
+            (None, Some(_)) => !username.is_empty() && m.username.eq_ignore_ascii_case(username),
+            (_, None) => m.first_name.eq_ignore_ascii_case(name),
+        })
+    };
+    Ok(known_inventories()?.into_iter().filter(|(user_id, inventory)| aboard(*user_id, &inventory.name)).map(|(_, inventory)| inventory).collect())
+}
+
+/// The inventory of one player, preferring what they confirmed through /inventario.
+pub fn find_inventory(target: &Target) -> Result<Option<CrewInventory>, String> {
+    if let Some(user_id) = target.user_id {
+        if let Some(scanned) = read_inventories()?.remove(&user_id.to_string()) {
+            return Ok(Some(scanned.into()));
+        }
+    }
+    Ok(known_inventories()?
+        .into_iter()
+        .map(|(_, inventory)| inventory)
+        .find(|inventory| target.names.iter().any(|name| inventory.name.eq_ignore_ascii_case(name))))
+}
+
+/// Emojis differ only by the variation selector depending on the client, e.g. ⚡ and ⚡️.
+pub fn same_emoji(a: &str, b: &str) -> bool {
+    a.chars().filter(|c| *c != '\u{fe0f}').eq(b.chars().filter(|c| *c != '\u{fe0f}'))
+}
+
+/// Lists who holds tickets or recipes for the given role emoji.
+pub fn render_who_has(label: &str, emoji: &str, inventories: &[CrewInventory]) -> String {
+    let mut holders: Vec<(&str, u32, u32)> = inventories
+        .iter()
+        .map(|inventory| {
+            let count = |vip: bool| inventory.tickets.items.iter().filter(|t| t.vip == vip && same_emoji(&t.emoji, emoji)).map(|t| t.count).sum::<u32>();
+            (inventory.name.as_str(), count(false), count(true))
+        })
+        .filter(|(_, regular, vip)| regular + vip > 0)
+        .collect();
+    holders.sort_by_key(|(name, regular, vip)| (std::cmp::Reverse(regular + vip), *name));
+
+    let mut response = format!("🔎 Quem tem {}:\n\n🎟 Tickets:\n", label);
+    if holders.is_empty() {
+        response.push_str("Ninguém.\n");
+    }
+    for (name, regular, vip) in holders {
+        match vip {
+            0 => response.push_str(&format!("- {}: x{}\n", name, regular)),
+            _ => response.push_str(&format!("- {}: x{} (+{} VIP)\n", name, regular, vip)),
+        }
+    }
+
+    let cooks: Vec<&str> = inventories
+        .iter()
+        .filter(|inventory| inventory.recipes.iter().any(|r| same_emoji(r, emoji)))
+        .map(|inventory| inventory.name.as_str())
+        .collect();
+    response.push_str("\n📃 Receitas:\n");
+    if cooks.is_empty() {
+        response.push_str("Ninguém.\n");
+    }
+    for name in cooks {
+        response.push_str(&format!("- {}\n", name));
+    }
+    response
+}
+
+fn sorted_totals(totals: HashMap<String, u32>) -> Vec<(String, u32)> {
+    let mut totals: Vec<(String, u32)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+/// Sums tickets, recipes and pieces across the whole crew.
+pub fn render_team_totals(inventories: &[CrewInventory]) -> String {
+    let mut tickets: HashMap<String, u32> = HashMap::new();
+    let mut vip_tickets: HashMap<String, u32> = HashMap::new();
+    let mut recipes: HashMap<String, u32> = HashMap::new();
+    for inventory in inventories {
+        for ticket in &inventory.tickets.items {
+            let key = ticket.emoji.replace('\u{fe0f}', "");
+            *if ticket.vip { &mut vip_tickets } else { &mut tickets }.entry(key).or_default() += ticket.count;
+        }
+        for recipe in &inventory.recipes {
+            *recipes.entry(recipe.replace('\u{fe0f}', "")).or_default() += 1;
+        }
+    }
+    let pieces: u32 = inventories.iter().map(|i| i.pieces).sum();
+
+    let counts = |totals: HashMap<String, u32>| render_counts(sorted_totals(totals).iter().map(|(emoji, count)| (emoji.as_str(), *count)));
+    format!(
+        "💼 Inventário da Tripulação ({} jogadores):\n\n🎟 Tickets: \n\n{}\n\n🎫 VIP Tickets: \n\n{}\n\n📃 Receitas (jogadores que têm): \n\n{}\n\n{}",
+        inventories.len(),
+        counts(tickets),
+        counts(vip_tickets),
+        counts(recipes),
+        render_pieces(pieces)
+    )
+}
+
+/// Renders a parsed inventory through the typed model, as the commands show it.
+pub fn render_inventory(items: &[InventoryItem]) -> String {
+    let mut sections = vec![tickets_from_items(items).render()];
+    let recipes = recipes_from_items(items);
+    if !recipes.is_empty() {
+        sections.push(render_recipes(&recipes));
+    }
+    if items.iter().any(|i| i.category == ItemCategory::Piece) {
+        sections.push(render_pieces(pieces_from_items(items)));
+    }
+    sections.join("\n\n")
+}
+
+#[derive(Deserialize)]
+struct ExtractedInventory {
+    items: Vec<InventoryItem>,
+}
+
+pub fn read_inventories() -> Result<HashMap<String, PlayerInventory>, String> {
+    let data = fs::read_to_string(&config().files.inventarios).map_err(|e| e.to_string())?;
+    let inventories: HashMap<String, PlayerInventory> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(inventories)
+}
+
+fn write_inventories(inventories: &HashMap<String, PlayerInventory>) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(inventories).map_err(|e| e.to_string())?;
+    fs::write(&config().files.inventarios, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn read_pending() -> Result<HashMap<String, PlayerInventory>, String> {
+    let data = fs::read_to_string(&config().files.inventarios_pendentes).map_err(|e| e.to_string())?;
+    let pending: HashMap<String, PlayerInventory> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(pending)
+}
+
+fn write_pending(pending: &HashMap<String, PlayerInventory>) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(pending).map_err(|e| e.to_string())?;
+    fs::write(&config().files.inventarios_pendentes, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn header_category(header: &str) -> Option<ItemCategory> {
+    let header = fold(header);
+    if header.contains("vip") {
+        Some(ItemCategory::VipTicket)
+    } else if header.contains("selected") || header.contains("selecionado") {
+        Some(ItemCategory::Selected)
+    } else if header.contains("ticket") {
+        Some(ItemCategory::Ticket)
+    } else if header.contains("recipe") || header.contains("receita") {
+        Some(ItemCategory::Recipe)
+    } else if header.contains("piece") || header.contains("peca") {
+        Some(ItemCategory::Piece)
+    } else {
+        None
+    }
+}
+
+/// Parses `🐺🍖 x4` into the emoji and its count; a bare emoji counts once.
+fn parse_item(entry: &str, category: ItemCategory) -> Option<InventoryItem> {
+    let mut tokens: Vec<&str> = entry.split_whitespace().collect();
+    let count = match tokens.last().and_then(|t| t.strip_prefix('x')).and_then(|n| n.parse().ok()) {
+        Some(count) => {
+            tokens.pop();
+            count
+        }
+        None => 1,
+    };
+    let emoji = tokens.join(" ");
+    if emoji.is_empty() {
+        return None;
+    }
+    Some(InventoryItem { emoji, count, category })
+}
+
+/// Parses the inventory text of the Werewolf bot, as forwarded by players or
+/// as stored in the old hand-written files.
+pub fn parse_inventory_text(text: &str) -> Vec<InventoryItem> {
+    let mut items = Vec::new();
+    let mut category = None;
+    for line in text.lines() {
+        let mut content = line.trim();
+        if let Some((header, rest)) = content.split_once(':') {
+            if let Some(found) = header_category(header) {
+                category = Some(found);
+                content = rest.trim();
+            }
+        }
+        if content.is_empty() {
+            continue;
+        }
+        match category {
+            Some(ItemCategory::Piece) => {
+                let digits: String = content.chars().filter(|c| c.is_ascii_digit()).collect();
+                if let Ok(count) = digits.parse() {
+                    items.push(InventoryItem { emoji: "🧩".to_string(), count, category: ItemCategory::Piece });
+                }
+            }
+            // The whole "the Gunner 🔫", as the Werewolf bot names the ticket.
+            Some(ItemCategory::Selected) => items.push(InventoryItem { emoji: content.to_string(), count: 1, category: ItemCategory::Selected }),
+            Some(other) => items.extend(content.split(',').filter_map(|entry| parse_item(entry, other))),
+            None => {}
+        }
+    }
+    items
+}
+
+/// Asks the model to read an inventory from a screenshot or free-form text.
+pub async fn extract_inventory(image: Option<&Image>, text: &str, usage: &mut TokenUsage) -> Result<Vec<InventoryItem>, AskError> {
+    let gemini_api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not set");
+    let gemini = Gemini::new(gemini_api_key);
+
+    let schema = json!({
+        "type": "OBJECT",
+        "properties": {
+            "items": {
+                "type": "ARRAY",
+                "items": {
+                    "type": "OBJECT",
+                    "properties": {
+                        "emoji": { "type": "STRING" },
+                        "count": { "type": "INTEGER" },
+                        "category": { "type": "STRING", "enum": ["ticket", "vip_ticket", "recipe", "piece", "selected"] }
+                    },
+                    "required": ["emoji", "count", "category"]
+                }
+            }
+        },
+        "required": ["items"]
+    });
+    let instructions = format!(
+        "Extraia o inventário do jogo Lobisomem (Werewolf) {}. Cada ticket, ticket VIP ou receita é identificado pelo emoji do papel; \"x3\" indica quantidade 3. Peças são um único item 🧩 com a quantidade total. O ticket selecionado é um item com quantidade 1.\n\n{}",
+        if image.is_some() { "desta captura de tela" } else { "deste texto" },
+        text
+    );
+
+    let mut content = match image {
+        Some(image) => Content::inline_data(image.mime_type.clone(), image.data.clone()),
+        None => Content::default(),
+    };
+    content.parts.push(Part::Text { text: instructions, thought: None });
+    let message = GeminiMessage { content: content.with_role(Role::User), role: Role::User };
+
+    let response = with_retry(|| {
+        gemini
+            .generate_content()
+            .with_message(message.clone())
+            .with_response_mime_type("application/json")
+            .with_response_schema(schema.clone())
+            .execute()
+    })
+    .await?;
+    if let Some(metadata) = &response.usage_metadata {
+        usage.add(metadata);
+    }
+
+    let extracted: ExtractedInventory = serde_json::from_str(&response.text()).map_err(|e| AskError::Invalid(e.to_string()))?;
+    Ok(extracted.items)
+}
+
+/// Keeps the parsed inventory until the player confirms it.
+pub fn stage_inventory(user_id: u64, first_name: &str, username: &str, items: Vec<InventoryItem>) -> Result<(), String> {
+    let mut pending = read_pending()?;
+    pending.insert(
+        user_id.to_string(),
+        PlayerInventory {
+            first_name: first_name.to_string(),
+            username: username.to_string(),
+            updated_at: Local::now().to_rfc3339(),
+            items,
+        },
+    );
+    write_pending(&pending)
+}
+
+/// Moves the staged inventory over the stored one; `None` if nothing was staged.
+pub fn confirm_inventory(user_id: u64) -> Result<Option<PlayerInventory>, String> {
+    let mut pending = read_pending()?;
+    let Some(inventory) = pending.remove(&user_id.to_string()) else {
+        return Ok(None);
+    };
+    let mut inventories = read_inventories()?;
+    inventories.insert(user_id.to_string(), inventory.clone());
+    write_inventories(&inventories)?;
+    write_pending(&pending)?;
+    Ok(Some(inventory))
+}
+
+pub fn discard_inventory(user_id: u64) -> Result<bool, String> {
+    let mut pending = read_pending()?;
+    let removed = pending.remove(&user_id.to_string()).is_some();
+    write_pending(&pending)?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKETS: &str = r#"{
+        "tickets": "🎟 Tickets: \n\n🧪 x2,👨‍🌾,⚡️,👦,👁,😏 x2,⚒,🤕 x5,👤 x3,💂 x4,😾,🕵️ x5,🃏,👻 x3,🌟,🍃,🦹‍♂️ x4,🐺🍖 x4,🧨,🦉,🪓,🐺🌝 x2,👷 x2,🌀 x5,👑 x2,🔥 x3,🐺🤢 x2,👳 x2,🔪 x3,😴 x5,🐺❄️,🔮,🐺💨,👱 x2,👶 x2,👵🏻,🐺 x4,🐶 x2",
+        "vip_tickets": "🎫 VIP Tickets: \n\n 😏, 🦹‍♂️, 🐺🌝 x2, 👷 x2, 🔥, 🔪, 🐾, 🐺💨 x2, 🐺 x3",
+        "selected_ticket": "✅ Selected ticket:\nthe Gunner 🔫"
+    }"#;
+    const RECIPES: &str = r#""📃 Your recipes: \n\n 🦊, 🐺💨, 💂, 💋, 👼, 👻, 🌀, 🐺, 🥖, 🌟, 👱🌚, 🔫, 🍻, 🦉, 👷, 💤, ❌, 👱, 😴, 🤕, 🐺🤢, 🧨, 😾, ☮️, 👑, ⚒, 🔪, 🃏, 🎯, 👳, 👺, 👁""#;
+    const PIECES: &str = r#""🧩 Pieces: 1345""#;
+
+    #[test]
+    fn legacy_files_are_loaded() {
+        let tickets: Tickets = serde_json::from_str::<StoredTickets>(TICKETS).unwrap().into();
+        assert_eq!(tickets.items.iter().filter(|t| !t.vip).count(), 38);
+        assert_eq!(tickets.items.iter().filter(|t| t.vip).count(), 9);
+        assert!(tickets.items.iter().any(|t| t.emoji == "🐺🍖" && t.count == 4 && !t.vip));
+        assert!(tickets.items.iter().any(|t| t.emoji == "🐺" && t.count == 3 && t.vip));
+        assert_eq!(tickets.selected_ticket.as_deref(), Some("the Gunner 🔫"));
+
+        let recipes: Vec<String> = serde_json::from_str::<StoredRecipes>(RECIPES).unwrap().into();
+        assert_eq!(recipes.len(), 32);
+        assert_eq!(recipes.first().map(String::as_str), Some("🦊"));
+
+        let pieces: u32 = serde_json::from_str::<StoredPieces>(PIECES).unwrap().into();
+        assert_eq!(pieces, 1345);
+    }
+
+    #[test]
+    fn forwarded_text_keeps_the_same_selected_ticket() {
+        let items = parse_inventory_text("🎟 Tickets: \n\n🧪 x2,👨‍🌾\n\n✅ Selected ticket:\nthe Gunner 🔫");
+        assert_eq!(tickets_from_items(&items).selected_ticket.as_deref(), Some("the Gunner 🔫"));
+    }
+}
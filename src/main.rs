@@ -1,30 +1,73 @@
+mod chatlog;
+mod chats;
+mod config;
+mod context;
+mod digest;
+mod gemini;
+mod inventory;
+mod media;
+mod memory;
+mod persona;
+mod players;
+mod privacy;
+mod quota;
+mod search;
+mod stats;
+mod streaming;
+mod tools;
+mod validation;
+
 use dotenv::dotenv;
 use std::env;
 use frankenstein::client_reqwest::Bot;
-use frankenstein::methods::{GetUpdatesParams, SendMessageParams, SendPhotoParams};
+use frankenstein::methods::{GetUpdatesParams, SendChatActionParams, SendDocumentParams, SendMessageParams, SendPhotoParams};
 use frankenstein::updates::UpdateContent;
-use frankenstein::types::Message;
+use frankenstein::types::{ChatAction, Message, ReplyParameters, User};
 use frankenstein::input_file::InputFile;
 use frankenstein::AsyncTelegramApi;
+use tokio::sync::watch;
 use tokio::time::{sleep, Duration};
-use gemini_rust::{Gemini, Part};
+use gemini_rust::{Content, FunctionCall, Gemini, GenerationResponse, Message as GeminiMessage, Part, Role};
+use chatlog::{append_entry, LogEntry, REDACTED_TEXT};
+use chats::{known_chats, read_chat_file, settings_for_chat, update_chat_settings, write_chat_file};
+use config::config;
+use context::build_context;
+use gemini::{check_finish, next_chunk, with_retry, AskError};
+use inventory::{
+    confirm_inventory, crew_inventories, discard_inventory, extract_inventory, find_inventory, parse_inventory_text, render_inventory, render_pieces,
+    render_recipes, render_team_totals, render_who_has, same_emoji, stage_inventory, CrewInventory,
+};
+use media::{download_image, photo_file_id, Image};
+use memory::{clear_chat, find_thread, read_conversations, record_exchange, thread_key, Turn};
+use persona::{persona_for_chat, read_personas, render_system_prompt, set_chat_persona, Persona};
+use players::{
+    check_consistency, is_log_opt_out, observe_user, player_name, read_players, register_player, render_name_history, resolve_target,
+    set_log_opt_out, set_nick, Target,
+};
+use privacy::{erase_user, export_user, hide_user};
+use quota::{check_rate_limit, daily_budget_exceeded, record_usage, usage_report, TokenUsage};
+use search::{render_search, render_who_said};
+use stats::{mark_seen, render_statistics, DEFAULT_INACTIVE_DAYS};
+use streaming::{finish_reply, spawn_progress_editor, PLACEHOLDER_TEXT};
+use tools::{run_tool_calls, tool_call_message, will_tools};
+use validation::validate_data;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::fs;
 use chrono::{NaiveDate, Local};
 use std::collections::HashMap;
+use std::sync::Mutex;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Tickets {
-    tickets: String,
-    vip_tickets: String,
-    selected_ticket: String,
-}
+/// Upper bound on model/tool round trips for a single question.
+const MAX_TOOL_ROUNDS: usize = 4;
+
+/// Team files that hold a scoreboard, e.g. will.json.
+const TEAMS: [&str; 4] = ["will", "barbossa", "jack", "elizabeth"];
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Papel {
     name: String,
     emoji: String,
+    #[serde(default)]
     nicks: Vec<String>,
 }
 
@@ -39,6 +82,8 @@ struct Player {
     name: String,
     user: String,
     points: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -46,6 +91,7 @@ struct Game {
     date: String,
     time: String,
     day_of_week: String,
+    #[serde(default)]
     teams: Vec<String>,
     phase: String,
 }
@@ -60,93 +106,451 @@ struct Mission {
 struct CrewMember {
     username: String,
     first_name: String,
+    #[serde(default = "default_true")]
     is_crewmember: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<u64>,
+    /// Dates in `%Y-%m-%d`, set when the bot sees the member join or leave the chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    joined_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    left_at: Option<String>,
+    /// Date in `%Y-%m-%d` of the member's latest message of any kind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_seen: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct Crew {
+    #[serde(default)]
     captain: Vec<CrewMember>,
+    #[serde(default)]
     leader: Vec<CrewMember>,
-    #[serde(rename = "sub-leader")]
+    #[serde(default, rename = "sub-leader")]
     sub_leader: Vec<CrewMember>,
+    #[serde(default)]
     crew: Vec<CrewMember>,
+    #[serde(default)]
     subs: Vec<CrewMember>,
 }
 
-fn read_tickets() -> Result<HashMap<String, Tickets>, String> {
-    let data = fs::read_to_string("tickets.json").map_err(|e| e.to_string())?;
-    let tickets: HashMap<String, Tickets> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    Ok(tickets)
+/// Crew ranks from lowest to highest, matching the vectors in tripulantes.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Sub,
+    Crew,
+    SubLeader,
+    Leader,
+    Captain,
 }
 
-fn read_receitas() -> Result<HashMap<String, String>, String> {
-    let data = fs::read_to_string("receitas.json").map_err(|e| e.to_string())?;
-    let receitas: HashMap<String, String> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    Ok(receitas)
+impl Rank {
+    const ALL: [Rank; 5] = [Rank::Sub, Rank::Crew, Rank::SubLeader, Rank::Leader, Rank::Captain];
+
+    fn title(self) -> &'static str {
+        match self {
+            Rank::Sub => "Sub",
+            Rank::Crew => "Tripulante",
+            Rank::SubLeader => "Sub-Líder",
+            Rank::Leader => "Líder",
+            Rank::Captain => "Capitão",
+        }
+    }
+
+    fn above(self) -> Option<Rank> {
+        Rank::ALL.get(self as usize + 1).copied()
+    }
+
+    fn below(self) -> Option<Rank> {
+        (self as usize).checked_sub(1).map(|i| Rank::ALL[i])
+    }
 }
 
-fn read_pecas() -> Result<HashMap<String, String>, String> {
-    let data = fs::read_to_string("pecas.json").map_err(|e| e.to_string())?;
-    let pecas: HashMap<String, String> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    Ok(pecas)
+impl Crew {
+    fn rank_members(&mut self, rank: Rank) -> &mut Vec<CrewMember> {
+        match rank {
+            Rank::Sub => &mut self.subs,
+            Rank::Crew => &mut self.crew,
+            Rank::SubLeader => &mut self.sub_leader,
+            Rank::Leader => &mut self.leader,
+            Rank::Captain => &mut self.captain,
+        }
+    }
+
+    /// The rank of an active member, looked up by username.
+    fn rank_of(&mut self, username: &str) -> Option<Rank> {
+        let username = username.trim_start_matches('@');
+        Rank::ALL
+            .into_iter()
+            .find(|rank| self.rank_members(*rank).iter().any(|m| m.is_crewmember && m.username.eq_ignore_ascii_case(username)))
+    }
+
+    /// Moves an active member to another rank, keeping at most one captain aboard.
+    fn set_rank(&mut self, username: &str, to: Rank) -> Result<(), String> {
+        let username = username.trim_start_matches('@');
+        let from = self.rank_of(username).ok_or_else(|| format!("@{} não está na tripulação.", username))?;
+        if to == Rank::Captain && self.captain.iter().any(|m| m.is_crewmember) {
+            return Err("O Holandês Voador já tem um capitão.".to_string());
+        }
+        let members = self.rank_members(from);
+        let index = members.iter().position(|m| m.is_crewmember && m.username.eq_ignore_ascii_case(username)).expect("rank_of found the member");
+        let member = members.remove(index);
+        self.rank_members(to).push(member);
+        Ok(())
+    }
+
+    fn all_members(&self) -> impl Iterator<Item = &CrewMember> {
+        self.captain.iter().chain(&self.leader).chain(&self.sub_leader).chain(&self.crew).chain(&self.subs)
+    }
+
+    fn all_members_mut(&mut self) -> impl Iterator<Item = &mut CrewMember> {
+        self.captain
+            .iter_mut()
+            .chain(&mut self.leader)
+            .chain(&mut self.sub_leader)
+            .chain(&mut self.crew)
+            .chain(&mut self.subs)
+    }
+
+    fn find_member_mut(&mut self, user: &User) -> Option<&mut CrewMember> {
+        let username = user.username.as_deref().unwrap_or_default();
+        self.all_members_mut().find(|m| match m.user_id {
+            Some(id) => id == user.id,
+            None => !username.is_empty() && m.username.eq_ignore_ascii_case(username),
+        })
+    }
+
+    /// Takes the first member matching `is_member` out of whichever rank lists them.
+    fn take_member(&mut self, is_member: impl Fn(&CrewMember) -> bool) -> Option<CrewMember> {
+        Rank::ALL.into_iter().find_map(|rank| {
+            let members = self.rank_members(rank);
+            let index = members.iter().position(&is_member)?;
+            Some(members.remove(index))
+        })
+    }
+
+    /// Adds a member to the subs. A returning member keeps their last-seen date but not
+    /// their old rank, so nobody regains admin rights or the captaincy just by rejoining.
+    fn join(&mut self, user: &User) {
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let username = user.username.as_deref().unwrap_or_default();
+        let returning = self.take_member(|m| match m.user_id {
+            Some(id) => id == user.id,
+            None => !username.is_empty() && m.username.eq_ignore_ascii_case(username),
+        });
+        self.subs.push(CrewMember {
+            username: username.to_string(),
+            first_name: user.first_name.clone(),
+            is_crewmember: true,
+            user_id: Some(user.id),
+            joined_at: Some(today),
+            left_at: None,
+            last_seen: returning.and_then(|m| m.last_seen),
+        });
+    }
+
+    /// Takes an active member off the crew for `/remover`. They stay listed among the subs,
+    /// no longer aboard, so they keep no rank should they rejoin.
+    fn remove(&mut self, username: &str) -> Result<(), String> {
+        let username = username.trim_start_matches('@');
+        let mut member = self
+            .take_member(|m| m.is_crewmember && m.username.eq_ignore_ascii_case(username))
+            .ok_or_else(|| format!("@{} não está na tripulação.", username))?;
+        member.is_crewmember = false;
+        member.left_at = Some(Local::now().format("%Y-%m-%d").to_string());
+        self.subs.push(member);
+        Ok(())
+    }
+
+    /// Merges the single-group crew list into this chat's. The legacy rank wins, since members
+    /// seen joining since the upgrade all start as subs; ids and dates recorded since are kept.
+    fn adopt(&mut self, mut legacy: Crew) {
+        let same_member = |a: &CrewMember, b: &CrewMember| match (a.user_id, b.user_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => !a.username.is_empty() && a.username.eq_ignore_ascii_case(&b.username),
+        };
+        for rank in Rank::ALL {
+            for member in std::mem::take(legacy.rank_members(rank)) {
+                let current = self.take_member(|m| same_member(m, &member));
+                let merged = match current {
+                    Some(current) => CrewMember {
+                        user_id: current.user_id.or(member.user_id),
+                        joined_at: current.joined_at.or(member.joined_at),
+                        last_seen: current.last_seen.or(member.last_seen),
+                        ..current
+                    },
+                    None => member,
+                };
+                self.rank_members(rank).push(merged);
+            }
+        }
+    }
+
+    /// Keeps departed members listed, but no longer aboard, so their history survives a rejoin.
+    fn leave(&mut self, user: &User) -> bool {
+        let Some(member) = self.find_member_mut(user) else {
+            return false;
+        };
+        member.user_id = Some(user.id);
+        member.is_crewmember = false;
+        member.left_at = Some(Local::now().format("%Y-%m-%d").to_string());
+        true
+    }
 }
 
 fn read_papeis() -> Result<Vec<Papel>, String> {
-    let data = fs::read_to_string("papeis.json").map_err(|e| e.to_string())?;
+    let data = fs::read_to_string(&config().files.papeis).map_err(|e| e.to_string())?;
     let papeis: Vec<Papel> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(papeis)
 }
 
-fn read_claims() -> Result<HashMap<String, Claim>, String> {
-    let data = fs::read_to_string("claims.json").map_err(|e| e.to_string())?;
+fn read_claims(chat_id: i64) -> Result<HashMap<String, Claim>, String> {
+    let Some(data) = read_chat_file(chat_id, &config().files.claims, false)? else {
+        return Ok(HashMap::new());
+    };
     let claims: HashMap<String, Claim> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(claims)
 }
 
-fn write_claims(claims: &HashMap<String, Claim>) -> Result<(), String> {
+fn write_claims(chat_id: i64, claims: &HashMap<String, Claim>) -> Result<(), String> {
     let data = serde_json::to_string_pretty(claims).map_err(|e| e.to_string())?;
-    fs::write("claims.json", data).map_err(|e| e.to_string())?;
-    Ok(())
+    write_chat_file(chat_id, &config().files.claims, &data)
 }
 
 fn read_team(team_name: &str) -> Result<Vec<Player>, String> {
-    let file_path = format!("{}.json", team_name);
+    let file_path = config().files.team(team_name)?;
     let data = fs::read_to_string(file_path).map_err(|e| e.to_string())?;
     let players: Vec<Player> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(players)
 }
 
-fn read_calendar() -> Result<Vec<Game>, String> {
-    let data = fs::read_to_string("calendario.json").map_err(|e| e.to_string())?;
+fn write_team(team_name: &str, players: &[Player]) -> Result<(), String> {
+    let file_path = config().files.team(team_name)?;
+    let data = serde_json::to_string_pretty(players).map_err(|e| e.to_string())?;
+    fs::write(file_path, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Reads a team file with its players ranked by points.
+fn read_scoreboard(team_name: &str) -> Result<Vec<Player>, String> {
+    let mut players = read_team(team_name)?;
+    players.sort_by_key(|p| std::cmp::Reverse(p.points));
+    Ok(players)
+}
+
+fn read_calendar(chat_id: i64) -> Result<Vec<Game>, String> {
+    let Some(data) = read_chat_file(chat_id, &config().files.calendario, true)? else {
+        return Ok(Vec::new());
+    };
     let games: Vec<Game> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(games)
 }
 
-fn read_missions() -> Result<Mission, String> {
-    let data = fs::read_to_string("missoes.json").map_err(|e| e.to_string())?;
+/// Picks the team's earliest game happening today or later.
+fn find_next_game(games: Vec<Game>, team: &str, today: NaiveDate) -> Option<Game> {
+    let my_team_games = games.into_iter().filter(|game| game.teams.iter().any(|t| t == team)).collect::<Vec<Game>>();
+    let mut next_game: Option<(NaiveDate, Game)> = None;
+
+    for game in my_team_games {
+        // Dates like "31/02" are reported by /verificar and skipped here.
+        let Ok(game_date) = NaiveDate::parse_from_str(&format!("{}/2025", game.date), "%d/%m/%Y") else {
+            continue;
+        };
+        if game_date >= today && next_game.as_ref().is_none_or(|(next_date, _)| game_date < *next_date) {
+            next_game = Some((game_date, game));
+        }
+    }
+
+    next_game.map(|(_, game)| game)
+}
+
+fn read_missions(chat_id: i64) -> Result<Mission, String> {
+    let data = read_chat_file(chat_id, &config().files.missoes, true)?.ok_or("Nenhuma missão cadastrada.")?;
     let mission: Mission = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(mission)
 }
 
-fn read_crew() -> Result<Crew, String> {
-    let data = fs::read_to_string("tripulantes.json").map_err(|e| e.to_string())?;
+fn read_crew(chat_id: i64) -> Result<Crew, String> {
+    let Some(data) = read_chat_file(chat_id, &config().files.tripulantes, false)? else {
+        return Ok(Crew::default());
+    };
     let crew: Crew = serde_json::from_str(&data).map_err(|e| e.to_string())?;
     Ok(crew)
 }
 
-fn write_crew(crew: &Crew) -> Result<(), String> {
+/// Every message runs in its own task, so crew read-modify-write cycles take turns.
+static CREW_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads a chat's crew, applies `change` and writes it back if anything changed, all while
+/// holding the crew lock so a concurrent update is never overwritten by a stale copy.
+fn update_crew<T>(chat_id: i64, change: impl FnOnce(&mut Crew) -> Result<T, String>) -> Result<T, String> {
+    let _guard = CREW_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut crew = read_crew(chat_id)?;
+    let before = serde_json::to_string(&crew).map_err(|e| e.to_string())?;
+    let result = change(&mut crew)?;
+    if serde_json::to_string(&crew).map_err(|e| e.to_string())? != before {
+        write_crew(chat_id, &crew)?;
+    }
+    Ok(result)
+}
+
+fn write_crew(chat_id: i64, crew: &Crew) -> Result<(), String> {
     let data = serde_json::to_string_pretty(crew).map_err(|e| e.to_string())?;
-    fs::write("tripulantes.json", data).map_err(|e| e.to_string())?;
-    Ok(())
+    write_chat_file(chat_id, &config().files.tripulantes, &data)
+}
+
+/// Captains and leaders of the chat's crew, plus any user id listed in `ADMIN_IDS`.
+fn is_admin(chat_id: i64, user: &User) -> bool {
+    let admin_ids = env::var("ADMIN_IDS").unwrap_or_default();
+    if admin_ids.split(',').any(|id| id.trim() == user.id.to_string()) {
+        return true;
+    }
+    let Some(username) = &user.username else {
+        return false;
+    };
+    match read_crew(chat_id) {
+        Ok(crew) => crew.captain.iter().chain(crew.leader.iter()).any(|m| m.is_crewmember && m.username.eq_ignore_ascii_case(username)),
+        Err(_) => false,
+    }
+}
+
+/// Promotes, demotes or removes a crew member on behalf of `caller`. Leaders may not
+/// touch the captain or hand out the captaincy; ids in `ADMIN_IDS` may do anything.
+fn change_rank(chat_id: i64, caller: &User, command: &str, arg: &str, reply_to: Option<&User>) -> Result<String, String> {
+    if !is_admin(chat_id, caller) {
+        return Err("Apenas o capitão e os líderes podem mudar a tripulação.".to_string());
+    }
+    let username = match (arg.strip_prefix('@'), reply_to) {
+        (Some(username), _) => username.to_string(),
+        (None, Some(user)) if arg.is_empty() => user.username.clone().ok_or("Esse tripulante não tem @usuário.")?,
+        _ => return Err(format!("Use {} @usuário ou responda a uma mensagem do tripulante.", command)),
+    };
+
+    update_crew(chat_id, |crew| {
+        let caller_rank = caller.username.as_deref().and_then(|u| crew.rank_of(u));
+        let rank = crew.rank_of(&username).ok_or_else(|| format!("@{} não está na tripulação.", username))?;
+        let is_leader = caller_rank.is_some_and(|r| r < Rank::Captain);
+        if is_leader && rank == Rank::Captain {
+            return Err("Só o capitão pode mudar o posto do capitão.".to_string());
+        }
+
+        let announcement = match command {
+            "/promover" => {
+                let to = rank.above().ok_or_else(|| format!("@{} já é o capitão.", username))?;
+                if is_leader && to == Rank::Captain {
+                    return Err("Só o capitão pode passar o comando do navio.".to_string());
+                }
+                crew.set_rank(&username, to)?;
+                format!("⬆️ @{} foi promovido a {}.", username, to.title())
+            }
+            "/rebaixar" => {
+                let to = rank.below().ok_or_else(|| format!("@{} já está no posto mais baixo.", username))?;
+                crew.set_rank(&username, to)?;
+                format!("⬇️ @{} foi rebaixado a {}.", username, to.title())
+            }
+            _ => {
+                crew.remove(&username)?;
+                format!("🚣 @{} foi removido da tripulação.", username)
+            }
+        };
+        Ok(announcement)
+    })
+}
+
+/// Resolves who an inventory command is about and loads their inventory.
+fn lookup_inventory(message: &Message, arg: &str) -> Result<(Target, Option<CrewInventory>), String> {
+    if let Some(user) = &message.from {
+        if let Err(err) = register_player(message.chat.id, user) {
+            println!("Failed to write players: {}", err);
+        }
+    }
+    let target = resolve_target(message, arg)?;
+    let inventory = find_inventory(&target)?;
+    Ok((target, inventory))
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
+    if let Err(err) = config::init() {
+        eprintln!("Failed to read the data directory config: {}", err);
+        std::process::exit(1);
+    }
+    if env::args().any(|arg| arg == "--init") {
+        match config::scaffold_data_dir() {
+            Ok(created) => {
+                println!("Initialised {} with {} new file(s):", config().data_dir.display(), created.len());
+                for path in created {
+                    println!("- {}", path.display());
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to initialise the data directory: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let args: Vec<String> = env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--adotar") {
+        let Some(chat_id) = args.get(position + 1).and_then(|id| id.parse::<i64>().ok()) else {
+            eprintln!("Usage: will-bot --adotar <chat_id>");
+            std::process::exit(1);
+        };
+        match config::adopt_legacy_files(chat_id) {
+            Ok(adopted) => {
+                println!("Merged {} file(s) into the directory of chat {}:", adopted.len(), chat_id);
+                for path in adopted {
+                    println!("- {}", path.display());
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to adopt the legacy files: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let missing = config::missing_files();
+    if !missing.is_empty() {
+        eprintln!("Missing data files in {}:", config().data_dir.display());
+        for path in &missing {
+            eprintln!("- {}", path.display());
+        }
+        eprintln!("Set DATA_DIR to the right directory, or run with --init to create empty files.");
+        std::process::exit(1);
+    }
+    // Chats only read their own directory, so an unadopted crew would silently lock out every leader.
+    let legacy = config::unadopted_legacy_files();
+    if !legacy.is_empty() {
+        eprintln!("Single-group files in {} are not used by any chat yet:", config().data_dir.display());
+        for path in &legacy {
+            eprintln!("- {}", path.display());
+        }
+        eprintln!("Run with --adotar <chat_id> to merge them into the group's directory.");
+        std::process::exit(1);
+    }
+
     let token = env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN not set");
     let bot = Bot::new(&token);
 
+    let problems = validate_data();
+    if problems.is_empty() {
+        println!("Data files OK");
+    } else {
+        println!("Found {} problem(s) in the data files:", problems.len());
+        for problem in &problems {
+            println!("- {}", problem);
+        }
+    }
+
+    digest::spawn_scheduler(bot.clone());
+
     let mut update_params = GetUpdatesParams::builder().build();
 
     println!("Bot is running...");
@@ -175,62 +579,83 @@ async fn main() {
 }
 
 async fn process_message(message: Message, bot: Bot) {
-    if let Some(text) = &message.text {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open("chat_log.txt")
-            .unwrap();
-
-        if let Some(user) = &message.from {
-            let log = format!("[{}] {}: {}\n", Local::now().to_rfc2822(), user.first_name, text);
-            if let Err(e) = writeln!(file, "{}", log) {
-                eprintln!("Couldn't write to file: {}", e);
+    if let Some(user) = &message.from {
+        if let Err(err) = observe_user(message.chat.id, user) {
+            println!("Failed to update player names: {}", err);
+        }
+        if let Err(err) = mark_seen(message.chat.id, user) {
+            println!("Failed to update crew activity: {}", err);
+        }
+    }
+
+    // Photos carry their command in the caption.
+    if let Some(text) = message.text.as_ref().or(message.caption.as_ref()) {
+        if let Some(mut entry) = LogEntry::from_message(&message, text) {
+            if entry.user_id.is_some_and(is_log_opt_out) {
+                entry.text = REDACTED_TEXT.to_string();
+            }
+            if let Err(err) = append_entry(&entry) {
+                println!("Failed to write chat log: {}", err);
             }
         }
 
-        if text == "/bemvindos" {
-            let send_photo_params = SendPhotoParams::builder()
-                .chat_id(message.chat.id)
-                .photo(frankenstein::input_file::FileUpload::InputFile(InputFile { path: "holandesvoador.jpg".into() }))
-                .caption("Bem-vindos ao Holandês Voador.")
-                .build();
+        // A reply to one of Will's messages continues that conversation.
+        let thread = message.reply_to_message.as_ref().and_then(|reply| {
+            read_conversations()
+                .ok()
+                .and_then(|conversations| find_thread(&conversations, message.chat.id, reply.message_id))
+        });
 
-            if let Err(err) = bot.send_photo(&send_photo_params).await {
-                println!("Failed to send photo: {:?}", err);
-            }
+        let settings = settings_for_chat(message.chat.id).unwrap_or_else(|err| {
+            println!("Failed to read chat settings: {}", err);
+            Default::default()
+        });
+        let command = match text.split_whitespace().next() {
+            Some(word) if word.starts_with('/') => word.split('@').next().unwrap_or(word).to_lowercase(),
+            _ if thread.is_some() => "/will".to_string(),
+            _ => String::new(),
+        };
+        if !command.is_empty() && !settings.is_enabled(&command) {
+            println!("Ignoring {} in chat {}: disabled", command, message.chat.id);
+            return;
+        }
 
-            let response_part1 = "Homens e mulheres do mar... escutem bem.\n\n\
-                            Vocês deixaram para trás a vida que conheciam. O tempo, para vocês, não passará da mesma forma que lá fora. A bordo deste navio, não há velhice — mas há serviço. E honra.\n\n\
-                            Sejam bem-vindos ao Holandês Voador.\n\
-                            Navegaremos por águas que nenhum outro navio ousa cruzar. Levaremos as almas dos que se afogam, dos que se perdem, dos que clamam por redenção. Nosso dever é eterno — mas não sem propósito.";
+        if text == "/bemvindos" {
+            let persona = match persona_for_chat(message.chat.id) {
+                Ok(persona) => persona,
+                Err(err) => {
+                    println!("Failed to read personas: {}", err);
+                    return;
+                }
+            };
 
-            let send_message_params1 = SendMessageParams::builder()
-                .chat_id(message.chat.id)
-                .text(response_part1)
-                .build();
-            if let Err(err) = bot.send_message(&send_message_params1).await {
-                println!("Failed to send message: {:?}", err);
-            }
+            if let Some(photo) = &persona.welcome.photo {
+                let send_photo_params = SendPhotoParams::builder()
+                    .chat_id(message.chat.id)
+                    .photo(frankenstein::input_file::FileUpload::InputFile(InputFile { path: config().data_dir.join(photo) }))
+                    .caption(persona.welcome.caption.clone())
+                    .build();
 
-            let response_part2 = "Alguns de vocês vieram por escolha. Outros... por necessidade. Mas todos aqui têm a segunda chance. E comigo no leme, não haverá açoite, nem traição, nem pactos quebrados. O Holandês já conheceu mentiras demais sob seu casco.\n\n\
-                            Vocês me servirão, e eu servirei a vocês.\n\
-                            Cada nó atado, cada vela içada, cada sino soado nesta embarcação carrega o peso de algo maior: a travessia entre mundos. Se honrarem esse navio e seus deveres, serão lembrados — mesmo nas águas mais escuras da lenda.\n\n\
-                            Então preparem-se, tripulação.\n\
-                            O mar nos chama, e o tempo já não nos pertence. Que os ventos soprem a nosso favor...\n\
-                            ...e que jamais esqueçam:\n\
-                            Aqui, sob a minha bandeira, a morte não é o fim — é apenas o começo.";
+                if let Err(err) = bot.send_photo(&send_photo_params).await {
+                    println!("Failed to send photo: {:?}", err);
+                }
+            }
 
-            let send_message_params2 = SendMessageParams::builder()
-                .chat_id(message.chat.id)
-                .text(response_part2)
-                .build();
-            if let Err(err) = bot.send_message(&send_message_params2).await {
-                println!("Failed to send message: {:?}", err);
+            for part in &persona.welcome.messages {
+                let send_message_params = SendMessageParams::builder()
+                    .chat_id(message.chat.id)
+                    .text(part.clone())
+                    .build();
+                if let Err(err) = bot.send_message(&send_message_params).await {
+                    println!("Failed to send message: {:?}", err);
+                }
+            }
+        } else if text.starts_with("/will") || (thread.is_some() && !text.starts_with('/')) {
+            let photo = photo_file_id(&message);
+            let mut question = text.trim_start_matches("/will").trim();
+            if question.is_empty() && photo.is_some() {
+                question = "O que você vê nesta imagem?";
             }
-        } else if text.starts_with("/will") {
-            let question = text.trim_start_matches("/will").trim();
             if question.is_empty() {
                 let send_message_params = SendMessageParams::builder()
                     .chat_id(message.chat.id)
@@ -241,30 +666,351 @@ async fn process_message(message: Message, bot: Bot) {
                 }
                 return;
             }
-            match ask_gemini(question).await {
+            let key = thread.unwrap_or_else(|| thread_key(message.chat.id, message.message_id));
+            let history = read_conversations()
+                .ok()
+                .and_then(|mut conversations| conversations.remove(&key))
+                .map(|conversation| conversation.turns)
+                .unwrap_or_default();
+            let persona = match persona_for_chat(message.chat.id) {
+                Ok(persona) => persona,
+                Err(err) => {
+                    println!("Failed to read personas: {}", err);
+                    return;
+                }
+            };
+            let user_id = message.from.as_ref().map_or(0, |u| u.id);
+            if daily_budget_exceeded() || !check_rate_limit(message.chat.id, user_id) {
+                let send_message_params = SendMessageParams::builder()
+                    .chat_id(message.chat.id)
+                    .text(persona.busy_message.clone())
+                    .reply_parameters(ReplyParameters::builder().message_id(message.message_id).build())
+                    .build();
+                if let Err(err) = bot.send_message(&send_message_params).await {
+                    println!("Failed to send message: {:?}", err);
+                }
+                return;
+            }
+
+            let typing_params = SendChatActionParams::builder()
+                .chat_id(message.chat.id)
+                .action(ChatAction::Typing)
+                .build();
+            if let Err(err) = bot.send_chat_action(&typing_params).await {
+                println!("Failed to send chat action: {:?}", err);
+            }
+
+            // Without a placeholder to edit, the answer falls back to a single message.
+            let placeholder_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(PLACEHOLDER_TEXT)
+                .reply_parameters(ReplyParameters::builder().message_id(message.message_id).build())
+                .build();
+            let placeholder = match bot.send_message(&placeholder_params).await {
+                Ok(sent) => Some(sent.result.message_id),
+                Err(err) => {
+                    println!("Failed to send message: {:?}", err);
+                    None
+                }
+            };
+            let (progress_tx, progress_rx) = watch::channel(String::new());
+            let editor = placeholder.map(|id| spawn_progress_editor(bot.clone(), message.chat.id, id, progress_rx));
+
+            let image = match &photo {
+                Some(file_id) => match download_image(&bot, file_id).await {
+                    Ok(image) => Some(image),
+                    Err(err) => {
+                        println!("Failed to download photo: {}", err);
+                        None
+                    }
+                },
+                None => None,
+            };
+
+            let mut usage = TokenUsage { requests: 1, ..Default::default() };
+            let result = ask_gemini(&persona, message.chat.id, question, image.as_ref(), &history, &progress_tx, &mut usage).await;
+            if let Some(editor) = editor {
+                editor.abort();
+                let _ = editor.await;
+            }
+
+            let user_name = message.from.as_ref().map_or("Unknown", |u| &u.first_name);
+            if let Err(err) = record_usage(message.chat.id, user_id, user_name, &usage) {
+                println!("Failed to write usage: {}", err);
+            }
+            match result {
                 Ok(response) => {
-                    let send_message_params = SendMessageParams::builder()
-                        .chat_id(message.chat.id)
-                        .text(response)
-                        .build();
-                    if let Err(err) = bot.send_message(&send_message_params).await {
-                        println!("Failed to send message: {:?}", err);
+                    let answer_ids = finish_reply(&bot, message.chat.id, message.message_id, placeholder, &response).await;
+                    if !answer_ids.is_empty() {
+                        if let Err(err) = record_exchange(&key, message.chat.id, user_id, &answer_ids, question, &response) {
+                            println!("Failed to write conversations: {}", err);
+                        }
                     }
                 }
                 Err(err) => {
-                    let send_message_params = SendMessageParams::builder()
-                        .chat_id(message.chat.id)
-                        .text(format!("Error asking Gemini: {}", err))
-                        .build();
-                    if let Err(err) = bot.send_message(&send_message_params).await {
-                        println!("Failed to send message: {:?}", err);
+                    println!("Failed to ask Gemini [{}]: {}", err.code(), err);
+                    let fallback = persona.error_message.replace("{codigo}", err.code());
+                    finish_reply(&bot, message.chat.id, message.message_id, placeholder, &fallback).await;
+                }
+            }
+        } else if text == "/limparmemoria" {
+            let response = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => match clear_chat(message.chat.id) {
+                    Ok(removed) => format!("Will esqueceu {} conversa(s) deste chat.", removed),
+                    Err(err) => {
+                        println!("Failed to write conversations: {}", err);
+                        return;
+                    }
+                },
+                _ => "Apenas o capitão e os líderes podem apagar a memória do Will.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text == "/uso" {
+            let response = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => usage_report().unwrap_or_else(|err| format!("Erro ao ler o uso: {}", err)),
+                _ => "Apenas o capitão e os líderes podem ver o uso do Will.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/estatisticas") {
+            let arg = text.trim_start_matches("/estatisticas").trim();
+            let days = if arg.is_empty() { Some(DEFAULT_INACTIVE_DAYS) } else { arg.parse::<i64>().ok().filter(|days| *days > 0) };
+            let response = match (&message.from, days) {
+                (Some(user), Some(days)) if is_admin(message.chat.id, user) => {
+                    render_statistics(message.chat.id, days).unwrap_or_else(|err| format!("Erro ao ler a atividade: {}", err))
+                }
+                (Some(user), None) if is_admin(message.chat.id, user) => "Use /estatisticas {dias sem falar}, por exemplo /estatisticas 14.".to_string(),
+                _ => "Apenas o capitão e os líderes podem ver as estatísticas.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/promover") || text.starts_with("/rebaixar") || text.starts_with("/remover") {
+            let (command, arg) = text.split_once(' ').unwrap_or((text, ""));
+            let reply_to = message.reply_to_message.as_ref().and_then(|reply| reply.from.as_deref());
+            let response = match message.from.as_deref() {
+                Some(user) => change_rank(message.chat.id, user, command, arg.trim(), reply_to).unwrap_or_else(|err| err),
+                None => return,
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/config") {
+            let arg = text.trim_start_matches("/config").trim();
+            let response = match &message.from {
+                Some(_) if arg.is_empty() => settings.render(),
+                Some(user) if is_admin(message.chat.id, user) => {
+                    let (key, value) = arg.split_once(' ').unwrap_or((arg, ""));
+                    update_chat_settings(message.chat.id, &key.to_lowercase(), value.trim()).map_or_else(|err| err, |settings| settings.render())
+                }
+                _ => "Apenas o capitão e os líderes podem mudar a configuração do chat.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text == "/verificar" {
+            let response = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => match validate_data() {
+                    problems if problems.is_empty() => "✅ Todos os arquivos de dados estão válidos.".to_string(),
+                    problems => format!("⚠️ {} problema(s) nos arquivos de dados:\n\n{}", problems.len(), problems.join("\n")),
+                },
+                _ => "Apenas o capitão e os líderes podem verificar os arquivos.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text == "/consistencia" {
+            let response = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => match check_consistency(message.chat.id) {
+                    Ok(report) if report.is_empty() => "✅ Todos os arquivos batem com o registro de jogadores.".to_string(),
+                    Ok(report) => format!("⚠️ {} problema(s) encontrado(s):\n\n{}", report.len(), report.join("\n")),
+                    Err(err) => format!("Erro ao ler o registro de jogadores: {}", err),
+                },
+                _ => "Apenas o capitão e os líderes podem verificar os arquivos.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/historico") {
+            let arg = text.trim_start_matches("/historico").trim();
+            let response = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => resolve_target(&message, arg)
+                    .and_then(|target| render_name_history(&target))
+                    .unwrap_or_else(|err| err),
+                _ => "Apenas o capitão e os líderes podem ver o histórico de nomes.".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/buscar") {
+            let response = render_search(message.chat.id, text.trim_start_matches("/buscar"));
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if command == "/quem" {
+            let query = text
+                .split_once(char::is_whitespace)
+                .and_then(|(_, rest)| rest.trim_start().strip_prefix("disse"))
+                .unwrap_or_default();
+            let response = render_who_said(message.chat.id, query);
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/privacidade") {
+            let Some(user) = &message.from else { return };
+            let response = match text.trim_start_matches("/privacidade").trim().to_lowercase().as_str() {
+                "" => if is_log_opt_out(user.id) {
+                    "🔒 Suas mensagens não são guardadas no histórico do chat. Use /privacidade on para voltar.".to_string()
+                } else {
+                    "📝 Suas mensagens são guardadas no histórico do chat por alguns dias. Use /privacidade off para sair.".to_string()
+                },
+                "off" => match set_log_opt_out(message.chat.id, user, true).and_then(|()| hide_user(user.id)) {
+                    Ok(redacted) => format!("🔒 Pronto, {}. Não guardo mais o que você escreve e ocultei {} mensagens antigas.", user.first_name, redacted),
+                    Err(err) => format!("Erro ao salvar a preferência: {}", err),
+                },
+                "on" => match set_log_opt_out(message.chat.id, user, false) {
+                    Ok(()) => format!("📝 Certo, {}. Suas próximas mensagens voltam a ser guardadas no histórico.", user.first_name),
+                    Err(err) => format!("Erro ao salvar a preferência: {}", err),
+                },
+                _ => "Use /privacidade [on | off].".to_string(),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text == "/esquecer" {
+            let Some(user) = &message.from else { return };
+            let response = match erase_user(user.id) {
+                Ok(removed) => format!("🧹 {}, apaguei {} mensagens suas do histórico dos chats.", user.first_name, removed),
+                Err(err) => format!("Erro ao apagar o histórico: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/exportar") {
+            let arg = text.trim_start_matches("/exportar").trim();
+            let export = match &message.from {
+                Some(user) if is_admin(message.chat.id, user) => resolve_target(&message, arg).and_then(|target| {
+                    let user_id = target.user_id.ok_or_else(|| format!("{} nunca falou com o bot, não há dados guardados.", target.label))?;
+                    // Another chat's leader must not read what the player wrote in a rival group.
+                    let chats: Vec<i64> = known_chats().into_iter().filter(|chat_id| is_admin(*chat_id, user)).collect();
+                    Ok((user.id, user_id, target.label, export_user(user_id, &chats)?))
+                }),
+                _ => Err("Apenas o capitão e os líderes podem exportar os dados de um jogador.".to_string()),
+            };
+            // The export goes to the admin's private chat, never to the group.
+            let response = match export {
+                Ok((admin_id, user_id, label, data)) => {
+                    let path = env::temp_dir().join(format!("dados_{}_{}.json", user_id, message.message_id));
+                    let sent = match fs::write(&path, data) {
+                        Ok(()) => {
+                            let send_document_params = SendDocumentParams::builder()
+                                .chat_id(admin_id as i64)
+                                .document(frankenstein::input_file::FileUpload::InputFile(InputFile { path: path.clone() }))
+                                .caption(format!("Dados guardados sobre {}.", label))
+                                .build();
+                            bot.send_document(&send_document_params).await.map_err(|err| format!("{:?}", err))
+                        }
+                        Err(err) => Err(err.to_string()),
+                    };
+                    let _ = fs::remove_file(&path);
+                    match sent {
+                        Ok(_) => format!("📦 Enviei no privado os dados de {}.", label),
+                        Err(err) => {
+                            println!("Failed to send export: {}", err);
+                            "Não consegui enviar no privado. Comece uma conversa comigo primeiro e tente de novo.".to_string()
+                        }
                     }
                 }
+                Err(err) => err,
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/persona") {
+            let persona_id = text.trim_start_matches("/persona").trim();
+            let response = if persona_id.is_empty() {
+                match (persona_for_chat(message.chat.id), read_personas()) {
+                    (Ok(current), Ok(personas)) => {
+                        let ids = personas.iter().map(|p| p.id.clone()).collect::<Vec<String>>().join(", ");
+                        format!("Persona atual: {} ({}).\nDisponíveis: {}", current.name, current.id, ids)
+                    }
+                    (Err(err), _) | (_, Err(err)) => format!("Erro ao ler as personas: {}", err),
+                }
+            } else {
+                match &message.from {
+                    Some(user) if is_admin(message.chat.id, user) => match set_chat_persona(message.chat.id, persona_id) {
+                        Ok(persona) => format!("{} assumiu o leme deste chat.", persona.name),
+                        Err(err) => err,
+                    },
+                    _ => "Apenas o capitão e os líderes podem trocar a persona.".to_string(),
+                }
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
             }
         } else if text == "/calendario" {
-            match read_calendar() {
+            match read_calendar(message.chat.id) {
                 Ok(games) => {
-                    let my_team_games = games.into_iter().filter(|game| game.teams.contains(&"🫀".to_string())).collect::<Vec<Game>>();
+                    let my_team_games = games.into_iter().filter(|game| game.teams.contains(&settings.team)).collect::<Vec<Game>>();
                     let mut response = "🗓 Calendário de Jogos do seu time:\n\n".to_string();
                     for game in my_team_games {
                         response.push_str(&format!("{} - {} às {} ({}) - {}\n", game.date, game.day_of_week, game.time, game.phase, game.teams.join(" vs ")));
@@ -288,25 +1034,9 @@ async fn process_message(message: Message, bot: Bot) {
                 }
             }
         } else if text == "/proximojogo" {
-            match read_calendar() {
+            match read_calendar(message.chat.id) {
                 Ok(games) => {
-                    let my_team_games = games.into_iter().filter(|game| game.teams.contains(&"🫀".to_string())).collect::<Vec<Game>>();
-                    let mut next_game: Option<Game> = None;
-                    let today = Local::now().date_naive();
-
-                    for game in my_team_games {
-                        let game_date = NaiveDate::parse_from_str(&format!("{}/2025", game.date), "%d/%m/%Y").unwrap();
-                        if game_date >= today {
-                            if let Some(next) = &next_game {
-                                let next_date = NaiveDate::parse_from_str(&format!("{}/2025", next.date), "%d/%m/%Y").unwrap();
-                                if game_date < next_date {
-                                    next_game = Some(game);
-                                }
-                            } else {
-                                next_game = Some(game);
-                            }
-                        }
-                    }
+                    let next_game = find_next_game(games, &settings.team, Local::now().date_naive());
 
                     if let Some(game) = next_game {
                         let response = format!("Próximo Jogo:\n\n{} - {} às {} ({}) - {}", game.date, game.day_of_week, game.time, game.phase, game.teams.join(" vs "));
@@ -338,7 +1068,7 @@ async fn process_message(message: Message, bot: Bot) {
                 }
             }
         } else if text == "/calendariocompleto" {
-            match read_calendar() {
+            match read_calendar(message.chat.id) {
                 Ok(games) => {
                     let mut response = "🗓 Calendário de Jogos Completo:\n\n".to_string();
                     for game in games {
@@ -363,7 +1093,7 @@ async fn process_message(message: Message, bot: Bot) {
                 }
             }
         } else if text == "/missoes" {
-            match read_missions() {
+            match read_missions(message.chat.id) {
                 Ok(mission) => {
                     let response = format!("{}\n\n{}", mission.title, mission.text);
                     let send_message_params = SendMessageParams::builder()
@@ -385,27 +1115,27 @@ async fn process_message(message: Message, bot: Bot) {
                 }
             }
         } else if text == "/tripulacao" {
-            match read_crew() {
+            match read_crew(message.chat.id) {
                 Ok(crew) => {
                     let mut response = "Tripulação do Holandês Voador:\n\n".to_string();
                     response.push_str("Capitão:\n");
-                    for member in crew.captain {
+                    for member in crew.captain.iter().filter(|m| m.is_crewmember) {
                         response.push_str(&format!("- {} (@{})\n", member.first_name, member.username));
                     }
                     response.push_str("\nLíder:\n");
-                    for member in crew.leader {
+                    for member in crew.leader.iter().filter(|m| m.is_crewmember) {
                         response.push_str(&format!("- {} (@{})\n", member.first_name, member.username));
                     }
                     response.push_str("\nSub-Líder:\n");
-                    for member in crew.sub_leader {
+                    for member in crew.sub_leader.iter().filter(|m| m.is_crewmember) {
                         response.push_str(&format!("- {} (@{})\n", member.first_name, member.username));
                     }
                     response.push_str("\nTripulantes:\n");
-                    for member in crew.crew {
+                    for member in crew.crew.iter().filter(|m| m.is_crewmember) {
                         response.push_str(&format!("- {} (@{})\n", member.first_name, member.username));
                     }
                     response.push_str("\nSubs:\n");
-                    for member in crew.subs {
+                    for member in crew.subs.iter().filter(|m| m.is_crewmember) {
                         response.push_str(&format!("- {} (@{})\n", member.first_name, member.username));
                     }
 
@@ -429,15 +1159,35 @@ async fn process_message(message: Message, bot: Bot) {
             }
         } else if text == "/comandos" {
             let response = "Comandos disponíveis:\n\n\
-/will [pergunta] - Faça uma pergunta para o Will Turner.\n\
+/will [pergunta] - Faça uma pergunta para o Will Turner (também como legenda ou resposta a uma foto).\n\
+/limparmemoria - Faz o Will esquecer as conversas deste chat (capitão e líderes).\n\
+/persona {id} - Mostra a persona do bot neste chat, ou a troca (capitão e líderes).\n\
+/uso - Mostra o consumo do Will hoje (capitão e líderes).\n\
+/config [time | persona | idioma | desativar | ativar | resumo | resumosemanal] {valor} - Mostra ou muda a configuração deste chat (capitão e líderes).\n\
+/verificar - Valida todos os arquivos de dados (capitão e líderes).\n\
+/consistencia - Confere os arquivos contra o registro de jogadores (capitão e líderes).\n\
+/historico [nome | @usuário] - Mostra os nomes antigos de um jogador (capitão e líderes).\n\
+/buscar {termo} - Mostra as mensagens recentes do chat que contêm o termo.\n\
+/quem disse {termo} - Mostra quem escreveu o termo no chat e quando.\n\
+/privacidade [on | off] - Mostra ou muda se as suas mensagens são guardadas no histórico do chat.\n\
+/esquecer - Apaga as suas mensagens do histórico dos chats.\n\
+/exportar [nome | @usuário] - Envia no privado tudo o que o bot guarda sobre um jogador (capitão e líderes).\n\
 /calendario - Mostra o calendário de jogos do seu time.\n\
 /proximojogo - Mostra o próximo jogo do seu time.\n\
 /calendariocompleto - Mostra o calendário de jogos completo.\n\
 /missoes - Mostra a pontuação das missões.\n\
 /tripulacao - Lista a tripulação do Holandês Voador.\n\
-/tickets {nome} - Mostra os tickets de um jogador.\n\
-/receitas {nome} - Mostra as receitas de um jogador.\n\
-/pecas {nome} - Mostra as peças de um jogador.\n\
+/estatisticas {dias} - Mostra a atividade de cada tripulante e quem está sem falar há mais de {dias} dias (capitão e líderes).\n\
+/promover @usuário - Sobe um tripulante de posto (capitão e líderes).\n\
+/rebaixar @usuário - Desce um tripulante de posto (capitão e líderes).\n\
+/remover @usuário - Tira um tripulante da lista (capitão e líderes).\n\
+/inventario - Lê o seu inventário de uma captura de tela ou texto encaminhado.\n\
+/inventariotime - Mostra o total de tickets, receitas e peças da tripulação.\n\
+/quemtem {emoji ou nick} - Lista quem tem tickets ou receitas de um papel.\n\
+/tickets [nome | @usuário] - Mostra os seus tickets ou os de um jogador (ou de quem você respondeu).\n\
+/receitas [nome | @usuário] - Mostra as suas receitas ou as de um jogador.\n\
+/pecas [nome | @usuário] - Mostra as suas peças ou as de um jogador.\n\
+/nick {nick} - Registra o seu nick no jogo.\n\
 /claim {nick} - Reivindica um papel.\n\
 /claims - Mostra a lista de papéis reivindicados.\n\
 /reset - Limpa a lista de papéis reivindicados.\n\
@@ -452,155 +1202,192 @@ async fn process_message(message: Message, bot: Bot) {
             if let Err(err) = bot.send_message(&send_message_params).await {
                 println!("Failed to send message: {:?}", err);
             }
-        } else if text.starts_with("/tickets") {
-            let args: Vec<&str> = text.split_whitespace().collect();
-            if args.len() < 2 {
+        } else if text == "/inventariotime" {
+            let response = match crew_inventories(message.chat.id) {
+                Ok(inventories) => render_team_totals(&inventories),
+                Err(err) => format!("Erro ao ler os inventários: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/quemtem") {
+            let query = text.trim_start_matches("/quemtem").trim();
+            if query.is_empty() {
                 let send_message_params = SendMessageParams::builder()
                     .chat_id(message.chat.id)
-                    .text("Por favor, forneça um nome após o comando /tickets.")
+                    .text("Por favor, forneça um emoji ou nick de papel após o comando /quemtem.")
                     .build();
                 if let Err(err) = bot.send_message(&send_message_params).await {
                     println!("Failed to send message: {:?}", err);
                 }
                 return;
             }
-            let name = args[1];
-            match read_tickets() {
-                Ok(tickets_map) => {
-                    if let Some(person_tickets) = tickets_map.get(name) {
-                        let response = format!(
-                            "💼 Your inventory:\n\n{}\n\n{}\n\n{}",
-                            person_tickets.tickets,
-                            person_tickets.vip_tickets,
-                            person_tickets.selected_ticket
-                        );
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
-                        }
-                    } else {
-                        let response = format!("Nenhum ticket encontrado para {}.", name);
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
-                        }
-                    }
-                }
-                Err(err) => {
-                    let send_message_params = SendMessageParams::builder()
-                        .chat_id(message.chat.id)
-                        .text(format!("Erro ao ler os tickets: {}", err))
-                        .build();
-                    if let Err(err) = bot.send_message(&send_message_params).await {
-                        println!("Failed to send message: {:?}", err);
-                    }
+            let response = match (read_papeis(), crew_inventories(message.chat.id)) {
+                (Ok(papeis), Ok(inventories)) => {
+                    let papel = papeis.iter().find(|p| p.nicks.iter().any(|n| n.eq_ignore_ascii_case(query)) || same_emoji(&p.emoji, query));
+                    let (label, emoji) = match papel {
+                        Some(papel) => (format!("{} {}", papel.name, papel.emoji), papel.emoji.as_str()),
+                        None => (query.to_string(), query),
+                    };
+                    render_who_has(&label, emoji, &inventories)
                 }
+                (Err(err), _) => format!("Erro ao ler os papéis: {}", err),
+                (_, Err(err)) => format!("Erro ao ler os inventários: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
             }
-        } else if text.starts_with("/receitas") {
-            let args: Vec<&str> = text.split_whitespace().collect();
-            if args.len() < 2 {
-                let send_message_params = SendMessageParams::builder()
-                    .chat_id(message.chat.id)
-                    .text("Por favor, forneça um nome após o comando /receitas.")
-                    .build();
-                if let Err(err) = bot.send_message(&send_message_params).await {
-                    println!("Failed to send message: {:?}", err);
-                }
+        } else if text.starts_with("/inventario") {
+            let Some(user) = &message.from else {
                 return;
-            }
-            let name = args[1];
-            match read_receitas() {
-                Ok(receitas_map) => {
-                    if let Some(receita) = receitas_map.get(name) {
-                        let response = format!("💼 Your inventory:\n\n{}", receita);
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
-                        }
-                    } else {
-                        let response = format!("Nenhuma receita encontrada para {}.", name);
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
+            };
+            let arg = text.trim_start_matches("/inventario").trim();
+            let response = if arg == "confirmar" {
+                match confirm_inventory(user.id) {
+                    Ok(Some(inventory)) => format!("Inventário de {} guardado no porão do Holandês: {} itens.", inventory.first_name, inventory.items.len()),
+                    Ok(None) => "Não há inventário aguardando confirmação. Envie /inventario com a captura de tela ou o texto do inventário.".to_string(),
+                    Err(err) => format!("Erro ao salvar o inventário: {}", err),
+                }
+            } else if arg == "cancelar" {
+                match discard_inventory(user.id) {
+                    Ok(true) => "Inventário descartado.".to_string(),
+                    Ok(false) => "Não há inventário aguardando confirmação.".to_string(),
+                    Err(err) => format!("Erro ao descartar o inventário: {}", err),
+                }
+            } else {
+                // Forwarded inventories are read from the message this one replies to.
+                let source_text = if arg.is_empty() {
+                    message.reply_to_message.as_ref().and_then(|reply| reply.text.clone()).unwrap_or_default()
+                } else {
+                    arg.to_string()
+                };
+                let photo = photo_file_id(&message);
+                let parsed = if photo.is_none() { parse_inventory_text(&source_text) } else { Vec::new() };
+                let needs_model = photo.is_some() || (parsed.is_empty() && !source_text.is_empty());
+                let mut usage = TokenUsage { requests: 1, ..Default::default() };
+                // Reading with the model counts against the same limits as /will.
+                let items = if needs_model && (daily_budget_exceeded() || !check_rate_limit(message.chat.id, user.id)) {
+                    Err(persona_for_chat(message.chat.id).map(|persona| persona.busy_message).unwrap_or_else(|err| format!("Erro ao ler as personas: {}", err)))
+                } else {
+                    let items = match &photo {
+                        Some(file_id) => match download_image(&bot, file_id).await {
+                            Ok(image) => extract_inventory(Some(&image), &source_text, &mut usage).await.map_err(|e| format!("Não consegui ler o inventário: {} ({})", e, e.code())),
+                            Err(err) => Err(format!("Não consegui ler o inventário: {}", err)),
+                        },
+                        None if needs_model => extract_inventory(None, &source_text, &mut usage).await.map_err(|e| format!("Não consegui ler o inventário: {} ({})", e, e.code())),
+                        None => Ok(parsed),
+                    };
+                    if usage.total_tokens() > 0 {
+                        if let Err(err) = record_usage(message.chat.id, user.id, &user.first_name, &usage) {
+                            println!("Failed to write usage: {}", err);
                         }
                     }
-                }
-                Err(err) => {
-                    let send_message_params = SendMessageParams::builder()
-                        .chat_id(message.chat.id)
-                        .text(format!("Erro ao ler as receitas: {}", err))
-                        .build();
-                    if let Err(err) = bot.send_message(&send_message_params).await {
-                        println!("Failed to send message: {:?}", err);
+                    items
+                };
+
+                match items {
+                    Ok(items) if items.is_empty() => "Não encontrei nenhum item. Envie /inventario como legenda da captura de tela, ou responda com /inventario ao inventário encaminhado do bot do Lobisomem.".to_string(),
+                    Ok(items) => {
+                        let preview = render_inventory(&items);
+                        match stage_inventory(user.id, &user.first_name, user.username.as_deref().unwrap_or_default(), items) {
+                            Ok(()) => format!("💼 Inventário lido:\n\n{}\n\nEnvie /inventario confirmar para substituir o inventário guardado, ou /inventario cancelar para descartar.", preview),
+                            Err(err) => format!("Erro ao guardar a prévia do inventário: {}", err),
+                        }
                     }
+                    Err(err) => err,
                 }
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .reply_parameters(ReplyParameters::builder().message_id(message.message_id).build())
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
             }
-        } else if text.starts_with("/pecas") {
-            let args: Vec<&str> = text.split_whitespace().collect();
-            if args.len() < 2 {
-                let send_message_params = SendMessageParams::builder()
-                    .chat_id(message.chat.id)
-                    .text("Por favor, forneça um nome após o comando /pecas.")
-                    .build();
-                if let Err(err) = bot.send_message(&send_message_params).await {
-                    println!("Failed to send message: {:?}", err);
+        } else if text.starts_with("/tickets") {
+            let arg = text.trim_start_matches("/tickets").trim();
+            let response = match lookup_inventory(&message, arg) {
+                Ok((target, Some(inventory))) if !inventory.tickets.items.is_empty() || inventory.tickets.selected_ticket.is_some() => {
+                    format!("💼 Inventário de {}:\n\n{}", target.label, inventory.tickets.render())
                 }
-                return;
+                Ok((target, _)) => format!("Nenhum ticket encontrado para {}.", target.label),
+                Err(err) => format!("Erro ao ler os tickets: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
             }
-            let name = args[1];
-            match read_pecas() {
-                Ok(pecas_map) => {
-                    if let Some(peca) = pecas_map.get(name) {
-                        let response = format!("💼 Your inventory:\n\n{}", peca);
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
-                        }
-                    } else {
-                        let response = format!("Nenhuma peça encontrada para {}.", name);
-                        let send_message_params = SendMessageParams::builder()
-                            .chat_id(message.chat.id)
-                            .text(response)
-                            .build();
-                        if let Err(err) = bot.send_message(&send_message_params).await {
-                            println!("Failed to send message: {:?}", err);
-                        }
-                    }
+        } else if text.starts_with("/receitas") {
+            let arg = text.trim_start_matches("/receitas").trim();
+            let response = match lookup_inventory(&message, arg) {
+                Ok((target, Some(inventory))) if !inventory.recipes.is_empty() => {
+                    format!("💼 Inventário de {}:\n\n{}", target.label, render_recipes(&inventory.recipes))
                 }
-                Err(err) => {
-                    let send_message_params = SendMessageParams::builder()
-                        .chat_id(message.chat.id)
-                        .text(format!("Erro ao ler as peças: {}", err))
-                        .build();
-                    if let Err(err) = bot.send_message(&send_message_params).await {
-                        println!("Failed to send message: {:?}", err);
-                    }
+                Ok((target, _)) => format!("Nenhuma receita encontrada para {}.", target.label),
+                Err(err) => format!("Erro ao ler as receitas: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/pecas") {
+            let arg = text.trim_start_matches("/pecas").trim();
+            let response = match lookup_inventory(&message, arg) {
+                Ok((target, Some(inventory))) if inventory.pieces > 0 => {
+                    format!("💼 Inventário de {}:\n\n{}", target.label, render_pieces(inventory.pieces))
                 }
+                Ok((target, _)) => format!("Nenhuma peça encontrada para {}.", target.label),
+                Err(err) => format!("Erro ao ler as peças: {}", err),
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
+            }
+        } else if text.starts_with("/nick") {
+            let nick = text.trim_start_matches("/nick").trim();
+            let response = match (&message.from, nick.is_empty()) {
+                (_, true) => "Por favor, forneça o seu nick do jogo após o comando /nick.".to_string(),
+                (Some(user), false) => match set_nick(message.chat.id, user, nick) {
+                    Ok(()) => format!("{}, seu nick no jogo agora é {}.", user.first_name, nick),
+                    Err(err) => format!("Erro ao salvar o nick: {}", err),
+                },
+                (None, false) => return,
+            };
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(response)
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send message: {:?}", err);
             }
         } else if text == "/claims" {
-            match read_claims() {
+            match read_claims(message.chat.id) {
                 Ok(claims) => {
+                    let players = read_players().unwrap_or_default();
                     let mut response = "📜 Lista de Claims:\n\n\n".to_string();
                     if claims.is_empty() {
                         response.push_str("Nenhum papel reivindicado ainda.");
                     } else {
-                        for (user, claim) in claims {
-                            response.push_str(&format!("-- {} :\t{} {}\n\n", user, claim.role_name, claim.role_emoji));
+                        for (key, claim) in claims {
+                            response.push_str(&format!("-- {} :\t{} {}\n\n", player_name(&players, &key), claim.role_name, claim.role_emoji));
                         }
                     }
                     let send_message_params = SendMessageParams::builder()
@@ -627,17 +1414,23 @@ async fn process_message(message: Message, bot: Bot) {
                 }
                 return;
             }
-            let user_name = message.from.as_ref().map_or("Unknown", |u| &u.first_name);
+            let Some(user) = message.from.as_deref() else {
+                return;
+            };
+            if let Err(err) = register_player(message.chat.id, user) {
+                println!("Failed to register player: {}", err);
+            }
+            let user_name = &user.first_name;
 
-            match (read_papeis(), read_claims()) {
+            match (read_papeis(), read_claims(message.chat.id)) {
                 (Ok(papeis), Ok(mut claims)) => {
                     if let Some(papel) = papeis.iter().find(|p| p.nicks.iter().any(|n| n.eq_ignore_ascii_case(nick))) {
                         let claim = Claim {
                             role_name: papel.name.clone(),
                             role_emoji: papel.emoji.clone(),
                         };
-                        claims.insert(user_name.to_string(), claim);
-                        if let Err(err) = write_claims(&claims) {
+                        claims.insert(user.id.to_string(), claim);
+                        if let Err(err) = write_claims(message.chat.id, &claims) {
                             println!("Failed to write claims: {}", err);
                         } else {
                             let response = format!("{} reivindicou o papel: {} {}", user_name, papel.name, papel.emoji);
@@ -665,7 +1458,7 @@ async fn process_message(message: Message, bot: Bot) {
             }
         } else if text == "/reset" {
             let claims: HashMap<String, Claim> = HashMap::new();
-            if let Err(err) = write_claims(&claims) {
+            if let Err(err) = write_claims(message.chat.id, &claims) {
                 println!("Failed to write claims: {}", err);
             } else {
                 let send_message_params = SendMessageParams::builder()
@@ -678,9 +1471,8 @@ async fn process_message(message: Message, bot: Bot) {
             }
         } else if text == "/barbossa" || text == "/jack" || text == "/elizabeth" {
             let team_name = text.trim_start_matches('/');
-            match read_team(team_name) {
-                Ok(mut players) => {
-                    players.sort_by(|a, b| b.points.cmp(&a.points));
+            match read_scoreboard(team_name) {
+                Ok(players) => {
                     let mut response = format!("🏆 Pontuação do Time {} 🏆\n\n", team_name.to_uppercase());
                     for (i, player) in players.iter().enumerate() {
                         response.push_str(&format!("{}. {} ({}): {} pontos\n", i + 1, player.name, player.user, player.points));
@@ -708,19 +1500,20 @@ async fn process_message(message: Message, bot: Bot) {
 
     if let Some(new_chat_members) = message.new_chat_members {
         for user in new_chat_members {
-            if let Ok(mut crew) = read_crew() {
-                let new_member = CrewMember {
-                    username: user.username.clone().unwrap_or_default(),
-                    first_name: user.first_name.clone(),
-                    is_crewmember: true,
-                };
-                crew.subs.push(new_member);
-                if let Err(err) = write_crew(&crew) {
-                    println!("Failed to write crew file: {}", err);
-                }
+            if let Err(err) = update_crew(message.chat.id, |crew| {
+                crew.join(&user);
+                Ok(())
+            }) {
+                println!("Failed to update crew file: {}", err);
             }
 
-            let text = format!("Bem-vindo a bordo {}. O Holandês Voador agora é seu lar", user.first_name);
+            let text = match persona_for_chat(message.chat.id) {
+                Ok(persona) => persona.welcome.new_member.replace("{nome}", &user.first_name),
+                Err(err) => {
+                    println!("Failed to read personas: {}", err);
+                    continue;
+                }
+            };
             let send_message_params = SendMessageParams::builder()
                 .chat_id(message.chat.id)
                 .text(text)
@@ -730,37 +1523,230 @@ async fn process_message(message: Message, bot: Bot) {
             }
         }
     }
+
+    if let Some(user) = message.left_chat_member {
+        if let Err(err) = update_crew(message.chat.id, |crew| Ok(crew.leave(&user))) {
+            println!("Failed to update crew file: {}", err);
+        }
+
+        let farewell = match persona_for_chat(message.chat.id) {
+            Ok(persona) => persona.welcome.farewell,
+            Err(err) => {
+                println!("Failed to read personas: {}", err);
+                None
+            }
+        };
+        if let Some(farewell) = farewell {
+            let send_message_params = SendMessageParams::builder()
+                .chat_id(message.chat.id)
+                .text(farewell.replace("{nome}", &user.first_name))
+                .build();
+            if let Err(err) = bot.send_message(&send_message_params).await {
+                println!("Failed to send farewell message: {:?}", err);
+            }
+        }
+    }
 }
 
-async fn ask_gemini(question: &str) -> Result<String, String> {
+/// Asks the persona a question. Tokens go into `usage` as they are spent, so failed
+/// questions still count against the daily budget.
+async fn ask_gemini(
+    persona: &Persona,
+    chat_id: i64,
+    question: &str,
+    image: Option<&Image>,
+    history: &[Turn],
+    progress: &watch::Sender<String>,
+    usage: &mut TokenUsage,
+) -> Result<String, AskError> {
     let gemini_api_key = env::var("GEMINI_API_KEY").expect("GEMINI_API_KEY not set");
     let gemini = Gemini::new(gemini_api_key);
 
-    let calendar_context = fs::read_to_string("calendario.json").unwrap_or_default();
-    let missions_context = fs::read_to_string("missoes.json").unwrap_or_default();
-    let chat_log_context = fs::read_to_string("chat_log.txt").unwrap_or_default();
-    let crew_context = fs::read_to_string("tripulantes.json").unwrap_or_default();
-
-    let context = format!(
-        "Contexto do Calendário:\n{}\n\nContexto das Missões:\n{}\n\nContexto do Chat:\n{}\n\nContexto da Tripulação:\n{}",
-        calendar_context, missions_context, chat_log_context, crew_context
-    );
-
-    let response = gemini
-        .generate_content()
-        .with_user_message(format!(
-            "Com base no seguinte contexto:\n\n{}\n\nVocê é Will Turner, Capitão do Holandês Voador, do filme Piratas do Caribe. Responda a seguinte pergunta como se você fosse Will Turner do filme Piratas do Caribe, em Português do Brasil. Seja criativo, e tente não narrar tanto: {}",
-            context, question
-        ))
-        .execute()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if let Some(part) = response.candidates[0].content.parts.get(0) {
-        if let Part::Text { text, .. } = part {
-            return Ok(text.clone());
+    // Follow-ups like "e depois?" inherit the topic of the earlier questions.
+    let mut topic: Vec<&str> = history.iter().filter(|t| t.role == "user").map(|t| t.text.as_str()).collect();
+    topic.push(question);
+    let sections = build_context(&gemini, chat_id, &topic.join(" "), usage).await;
+    let system_prompt = render_system_prompt(persona, &sections);
+
+    let mut messages: Vec<GeminiMessage> = Vec::new();
+    for example in &persona.examples {
+        messages.push(GeminiMessage::user(example.user.clone()));
+        messages.push(GeminiMessage::model(example.model.clone()));
+    }
+    for turn in history {
+        messages.push(if turn.role == "model" { GeminiMessage::model(turn.text.clone()) } else { GeminiMessage::user(turn.text.clone()) });
+    }
+    match image {
+        Some(image) => {
+            let mut content = Content::inline_data(image.mime_type.clone(), image.data.clone());
+            content.parts.push(Part::Text { text: question.to_string(), thought: None });
+            messages.push(GeminiMessage { content: content.with_role(Role::User), role: Role::User });
         }
+        None => messages.push(GeminiMessage::user(question)),
     }
 
-    Ok("".to_string())
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let request = || {
+            gemini
+                .generate_content()
+                .with_system_prompt(system_prompt.clone())
+                .with_messages(messages.clone())
+                .with_tool(will_tools())
+        };
+
+        let mut text = String::new();
+        let mut calls: Vec<FunctionCall> = Vec::new();
+        let mut truncated = false;
+        // Streamed chunks carry running totals, so only the last one counts.
+        let mut round_usage = None;
+        match with_retry(|| request().execute_stream()).await {
+            Ok(mut stream) => {
+                while let Some(chunk) = next_chunk(&mut stream).await? {
+                    truncated |= check_finish(&chunk)?;
+                    collect_parts(&chunk, &mut text, &mut calls);
+                    round_usage = chunk.usage_metadata.or(round_usage);
+                    progress.send_replace(text.clone());
+                }
+            }
+            // Outages are not worth a second round of retries on the other endpoint.
+            Err(err @ (AskError::Timeout | AskError::Network(_))) => return Err(err),
+            Err(err) => {
+                println!("Streaming unavailable, falling back to a single response: {}", err);
+                let response = with_retry(|| request().execute()).await?;
+                truncated = check_finish(&response)?;
+                collect_parts(&response, &mut text, &mut calls);
+                round_usage = response.usage_metadata;
+            }
+        }
+        if let Some(metadata) = &round_usage {
+            usage.add(metadata);
+        }
+
+        if calls.is_empty() {
+            if text.trim().is_empty() {
+                return Err(AskError::Empty);
+            }
+            if truncated {
+                println!("Gemini answer hit the output token limit");
+                text.push_str(" […]");
+            }
+            return Ok(text);
+        }
+
+        messages.push(tool_call_message(&calls));
+        messages.push(run_tool_calls(chat_id, &calls));
+    }
+
+    Err(AskError::ToolLoop)
+}
+
+/// Appends the visible text and any function calls of a (partial) response.
+fn collect_parts(response: &GenerationResponse, text: &mut String, calls: &mut Vec<FunctionCall>) {
+    for candidate in &response.candidates {
+        for part in &candidate.content.parts {
+            match part {
+                Part::Text { text: chunk, thought } if *thought != Some(true) => text.push_str(chunk),
+                Part::FunctionCall { function_call } => calls.push(function_call.clone()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u64, username: &str) -> User {
+        User::builder().id(id).is_bot(false).first_name(username.to_string()).username(username.to_string()).build()
+    }
+
+    fn member(id: u64, username: &str) -> CrewMember {
+        CrewMember {
+            username: username.to_string(),
+            first_name: username.to_string(),
+            is_crewmember: true,
+            user_id: Some(id),
+            joined_at: None,
+            left_at: None,
+            last_seen: Some("2024-01-01".to_string()),
+        }
+    }
+
+    #[test]
+    fn new_members_join_as_subs() {
+        let mut crew = Crew::default();
+        crew.join(&user(1, "jack"));
+        assert_eq!(crew.subs.len(), 1);
+        assert!(crew.subs[0].is_crewmember);
+        assert_eq!(crew.subs[0].user_id, Some(1));
+    }
+
+    #[test]
+    fn returning_members_rejoin_as_subs() {
+        let mut crew = Crew { captain: vec![member(1, "jack")], leader: vec![member(2, "will")], ..Default::default() };
+        crew.leave(&user(1, "jack"));
+        crew.leave(&user(2, "will"));
+        crew.join(&user(3, "elizabeth"));
+        crew.set_rank("elizabeth", Rank::Captain).unwrap();
+        crew.join(&user(1, "jack"));
+        crew.join(&user(2, "will"));
+
+        assert!(crew.leader.is_empty());
+        assert_eq!(crew.captain.iter().filter(|m| m.is_crewmember).count(), 1);
+        assert_eq!(crew.rank_of("jack"), Some(Rank::Sub));
+        assert_eq!(crew.rank_of("will"), Some(Rank::Sub));
+        let jack = crew.subs.iter().find(|m| m.username == "jack").unwrap();
+        assert_eq!(jack.user_id, Some(1));
+        assert_eq!(jack.last_seen.as_deref(), Some("2024-01-01"));
+        assert_eq!(jack.left_at, None);
+        assert_eq!(crew.all_members().count(), 3);
+    }
+
+    #[test]
+    fn leaving_members_stay_listed() {
+        let mut crew = Crew { leader: vec![member(2, "will")], ..Default::default() };
+        assert!(crew.leave(&user(2, "will")));
+        assert!(!crew.leader[0].is_crewmember);
+        assert!(crew.leader[0].left_at.is_some());
+        assert_eq!(crew.rank_of("will"), None);
+        assert!(!crew.leave(&user(3, "elizabeth")));
+    }
+
+    #[test]
+    fn removed_members_lose_their_rank() {
+        let mut crew = Crew { leader: vec![member(2, "will")], ..Default::default() };
+        crew.remove("@will").unwrap();
+        assert!(crew.leader.is_empty());
+        assert!(!crew.subs[0].is_crewmember);
+        assert!(crew.subs[0].left_at.is_some());
+        assert!(crew.remove("will").is_err());
+        crew.join(&user(2, "will"));
+        assert_eq!(crew.rank_of("will"), Some(Rank::Sub));
+    }
+
+    #[test]
+    fn only_one_captain_is_aboard() {
+        let mut crew = Crew { captain: vec![member(1, "jack")], crew: vec![member(2, "will")], ..Default::default() };
+        assert!(crew.set_rank("will", Rank::Captain).is_err());
+        crew.set_rank("@will", Rank::Leader).unwrap();
+        assert_eq!(crew.rank_of("will"), Some(Rank::Leader));
+        assert!(crew.crew.is_empty());
+        assert!(crew.set_rank("elizabeth", Rank::Crew).is_err());
+    }
+
+    #[test]
+    fn games_with_bad_dates_are_skipped() {
+        let game = |date: &str| Game {
+            date: date.to_string(),
+            time: "21:00".to_string(),
+            day_of_week: "Sábado".to_string(),
+            teams: vec!["will".to_string(), "jack".to_string()],
+            phase: "Grupos".to_string(),
+        };
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let next = find_next_game(vec![game("31/02"), game("20/03"), game("10/03"), game("01/01")], "will", today);
+        assert_eq!(next.map(|g| g.date).as_deref(), Some("10/03"));
+        assert!(find_next_game(vec![game("31/02")], "will", today).is_none());
+    }
 }
@@ -0,0 +1,47 @@
+use base64::{engine::general_purpose, Engine as _};
+use frankenstein::client_reqwest::Bot;
+use frankenstein::methods::GetFileParams;
+use frankenstein::types::Message;
+use frankenstein::AsyncTelegramApi;
+use std::env;
+
+/// Telegram re-encodes every photo as JPEG.
+const PHOTO_MIME_TYPE: &str = "image/jpeg";
+
+/// An image ready to be sent inline to the model.
+pub struct Image {
+    pub mime_type: String,
+    pub data: String,
+}
+
+/// The biggest size of the photo in the message, or in the message it replies to.
+pub fn photo_file_id(message: &Message) -> Option<String> {
+    message
+        .photo
+        .as_ref()
+        .or_else(|| message.reply_to_message.as_ref().and_then(|reply| reply.photo.as_ref()))
+        .and_then(|sizes| sizes.iter().max_by_key(|size| size.width * size.height))
+        .map(|size| size.file_id.clone())
+}
+
+/// Resolves the file through getFile and downloads it as base64.
+pub async fn download_image(bot: &Bot, file_id: &str) -> Result<Image, String> {
+    let get_file_params = GetFileParams::builder().file_id(file_id).build();
+    let file = bot.get_file(&get_file_params).await.map_err(|e| format!("{:?}", e))?.result;
+    let file_path = file.file_path.ok_or("Telegram não retornou o caminho do arquivo")?;
+
+    let token = env::var("TELEGRAM_BOT_TOKEN").map_err(|e| e.to_string())?;
+    let url = format!("https://api.telegram.org/file/bot{}/{}", token, file_path);
+    let bytes = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.without_url().to_string())?
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(Image {
+        mime_type: PHOTO_MIME_TYPE.to_string(),
+        data: general_purpose::STANDARD.encode(&bytes),
+    })
+}
@@ -0,0 +1,142 @@
+use crate::config::config;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+/// Rough upper bound for the history sent along with a follow-up question.
+const HISTORY_TOKEN_BUDGET: usize = 2000;
+/// Threads kept per chat; the ones answered longest ago are forgotten first.
+const MAX_THREADS_PER_CHAT: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Turn {
+    pub role: String,
+    pub text: String,
+    /// Who asked, on the user turns, so `/esquecer` and `/privacidade off` can drop them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conversation {
+    pub chat_id: i64,
+    /// Will's answers in the thread; replying to one of them continues it.
+    #[serde(default)]
+    pub message_ids: Vec<i32>,
+    #[serde(default)]
+    pub turns: Vec<Turn>,
+    /// RFC 3339 time of the latest answer in the thread.
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+pub fn read_conversations() -> Result<HashMap<String, Conversation>, String> {
+    let data = fs::read_to_string(&config().files.conversas).map_err(|e| e.to_string())?;
+    let conversations: HashMap<String, Conversation> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(conversations)
+}
+
+pub fn write_conversations(conversations: &HashMap<String, Conversation>) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(conversations).map_err(|e| e.to_string())?;
+    fs::write(&config().files.conversas, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Every `/will` runs in its own task, so conversation read-modify-write cycles take turns.
+static CONVERSATIONS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads the conversations, applies `change` and writes them back while holding the
+/// conversations lock, so overlapping answers never drop each other's turns.
+fn update_conversations<T>(change: impl FnOnce(&mut HashMap<String, Conversation>) -> T) -> Result<T, String> {
+    let _guard = CONVERSATIONS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut conversations = read_conversations()?;
+    let result = change(&mut conversations);
+    write_conversations(&conversations)?;
+    Ok(result)
+}
+
+/// A thread is keyed by the chat and the message that opened it with `/will`.
+pub fn thread_key(chat_id: i64, root_message_id: i32) -> String {
+    format!("{}:{}", chat_id, root_message_id)
+}
+
+/// Finds the thread of a replied-to message, if it is one of Will's answers.
+pub fn find_thread(conversations: &HashMap<String, Conversation>, chat_id: i64, message_id: i32) -> Option<String> {
+    conversations
+        .iter()
+        .find(|(_, c)| c.chat_id == chat_id && c.message_ids.contains(&message_id))
+        .map(|(key, _)| key.clone())
+}
+
+/// About four characters per token, which is close enough for Portuguese text.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Drops the oldest question/answer pairs until the history fits the budget.
+pub fn trim_history(turns: &mut Vec<Turn>) {
+    while turns.iter().map(|t| estimate_tokens(&t.text)).sum::<usize>() > HISTORY_TOKEN_BUDGET && turns.len() > 2 {
+        turns.drain(..2);
+    }
+}
+
+/// Keeps only the chat's most recently answered threads. Threads written before
+/// `updated_at` existed count as the oldest.
+fn forget_old_threads(conversations: &mut HashMap<String, Conversation>, chat_id: i64) {
+    let mut threads: Vec<(String, String)> =
+        conversations.iter().filter(|(_, c)| c.chat_id == chat_id).map(|(key, c)| (c.updated_at.clone(), key.clone())).collect();
+    if threads.len() <= MAX_THREADS_PER_CHAT {
+        return;
+    }
+    threads.sort_unstable();
+    for (_, key) in &threads[..threads.len() - MAX_THREADS_PER_CHAT] {
+        conversations.remove(key);
+    }
+}
+
+pub fn record_exchange(key: &str, chat_id: i64, user_id: u64, message_ids: &[i32], question: &str, answer: &str) -> Result<(), String> {
+    update_conversations(|conversations| {
+        let conversation = conversations.entry(key.to_string()).or_insert_with(|| Conversation {
+            chat_id,
+            message_ids: Vec::new(),
+            turns: Vec::new(),
+            updated_at: String::new(),
+        });
+        conversation.message_ids.extend_from_slice(message_ids);
+        conversation.turns.push(Turn { role: "user".to_string(), text: question.to_string(), user_id: Some(user_id) });
+        conversation.turns.push(Turn { role: "model".to_string(), text: answer.to_string(), user_id: None });
+        conversation.updated_at = Local::now().to_rfc3339();
+        trim_history(&mut conversation.turns);
+        forget_old_threads(conversations, chat_id);
+    })
+}
+
+pub fn clear_chat(chat_id: i64) -> Result<usize, String> {
+    update_conversations(|conversations| {
+        let before = conversations.len();
+        conversations.retain(|_, c| c.chat_id != chat_id);
+        before - conversations.len()
+    })
+}
+
+/// Drops every question a user asked Will, with its answer, from all threads.
+/// Threads left empty are forgotten. Returns how many questions were dropped.
+pub fn forget_turns(user_id: u64) -> Result<usize, String> {
+    update_conversations(|conversations| {
+        let mut removed = 0;
+        for conversation in conversations.values_mut() {
+            let turns = std::mem::take(&mut conversation.turns);
+            for pair in turns.chunks(2) {
+                if pair[0].user_id == Some(user_id) {
+                    removed += 1;
+                } else {
+                    conversation.turns.extend_from_slice(pair);
+                }
+            }
+        }
+        conversations.retain(|_, c| !c.turns.is_empty());
+        removed
+    })
+}
@@ -0,0 +1,104 @@
+use crate::chats::{settings_for_chat, update_chat_settings};
+use crate::config::config;
+use crate::context::ContextSection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Persona used by chats that never picked one.
+pub const DEFAULT_PERSONA: &str = "will";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExampleDialogue {
+    pub user: String,
+    pub model: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Welcome {
+    pub photo: Option<String>,
+    pub caption: String,
+    #[serde(default)]
+    pub messages: Vec<String>,
+    pub new_member: String,
+    /// Posted when someone leaves the chat; no message when unset.
+    #[serde(default)]
+    pub farewell: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Persona {
+    pub id: String,
+    pub name: String,
+    pub language: String,
+    pub system_prompt: String,
+    #[serde(default)]
+    pub style_rules: Vec<String>,
+    #[serde(default)]
+    pub examples: Vec<ExampleDialogue>,
+    pub welcome: Welcome,
+    /// Sent instead of an answer when rate limits or the daily budget are hit.
+    pub busy_message: String,
+    /// Sent when the model fails; `{codigo}` becomes the short error code.
+    pub error_message: String,
+}
+
+pub fn read_personas() -> Result<Vec<Persona>, String> {
+    let data = fs::read_to_string(&config().files.personas).map_err(|e| e.to_string())?;
+    let personas: Vec<Persona> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(personas)
+}
+
+/// Returns the persona selected for a chat, falling back to Will Turner, speaking
+/// the chat's language when one is set.
+pub fn persona_for_chat(chat_id: i64) -> Result<Persona, String> {
+    let personas = read_personas()?;
+    let settings = settings_for_chat(chat_id)?;
+    let selected = settings.persona.unwrap_or_else(|| DEFAULT_PERSONA.to_string());
+    let mut persona = personas
+        .iter()
+        .find(|p| p.id == selected)
+        .or_else(|| personas.iter().find(|p| p.id == DEFAULT_PERSONA))
+        .cloned()
+        .ok_or_else(|| format!("Persona '{}' não encontrada em personas.json", selected))?;
+    if let Some(language) = settings.language {
+        persona.language = language;
+    }
+    Ok(persona)
+}
+
+pub fn set_chat_persona(chat_id: i64, persona_id: &str) -> Result<Persona, String> {
+    let persona = read_personas()?
+        .into_iter()
+        .find(|p| p.id.eq_ignore_ascii_case(persona_id))
+        .ok_or_else(|| format!("Persona '{}' não encontrada.", persona_id))?;
+    update_chat_settings(chat_id, "persona", &persona.id)?;
+    Ok(persona)
+}
+
+/// Fills `{contexto}`, `{nome}`, `{idioma}` and per-section placeholders such as
+/// `{calendario}`; sections that were not selected for the question render empty.
+pub fn render_system_prompt(persona: &Persona, sections: &[ContextSection]) -> String {
+    let full_context = sections
+        .iter()
+        .map(|s| format!("{}:\n{}", s.title, s.body))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+
+    let mut prompt = persona
+        .system_prompt
+        .replace("{contexto}", &full_context)
+        .replace("{nome}", &persona.name)
+        .replace("{idioma}", &persona.language);
+    for key in ["calendario", "missoes", "pontuacao", "tripulacao", "resumo", "chat"] {
+        let body = sections.iter().find(|s| s.key == key).map_or("", |s| s.body.as_str());
+        prompt = prompt.replace(&format!("{{{}}}", key), body);
+    }
+
+    if !persona.style_rules.is_empty() {
+        prompt.push_str("\n\nRegras de estilo:\n");
+        for rule in &persona.style_rules {
+            prompt.push_str(&format!("- {}\n", rule));
+        }
+    }
+    prompt
+}
@@ -0,0 +1,411 @@
+use crate::config::config;
+use crate::inventory::{read_inventories, read_pecas, read_receitas, read_tickets};
+use crate::{read_claims, read_crew, read_team, update_crew, write_team, TEAMS};
+use chrono::{DateTime, Local};
+use frankenstein::types::{Message, User};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+/// The central record of a player, keyed by Telegram user id in jogadores.json.
+/// Other files point back to it by that id.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerIdentity {
+    pub username: String,
+    pub first_name: String,
+    #[serde(default)]
+    pub display_name: String,
+    pub nick: String,
+    /// Team file the player scores in, e.g. `will` for will.json.
+    #[serde(default)]
+    pub team: String,
+    /// Set with `/privacidade off`: their messages are logged without text.
+    #[serde(default)]
+    pub log_opt_out: bool,
+}
+
+impl PlayerIdentity {
+    fn names(&self) -> impl Iterator<Item = &String> {
+        [&self.first_name, &self.display_name, &self.nick, &self.username].into_iter().filter(|n| !n.is_empty())
+    }
+
+    pub fn answers_to(&self, name: &str) -> bool {
+        let name = name.trim_start_matches('@');
+        self.names().any(|n| n.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A rename seen on Telegram, kept in nomes_historico.json.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NameChange {
+    pub user_id: u64,
+    pub date: String,
+    pub old_username: String,
+    pub new_username: String,
+    pub old_first_name: String,
+    pub new_first_name: String,
+}
+
+/// Who a command is about: a known Telegram account and/or names to look up.
+pub struct Target {
+    pub user_id: Option<u64>,
+    pub label: String,
+    pub names: Vec<String>,
+}
+
+pub fn read_players() -> Result<HashMap<String, PlayerIdentity>, String> {
+    let data = fs::read_to_string(&config().files.jogadores).map_err(|e| e.to_string())?;
+    let players: HashMap<String, PlayerIdentity> = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(players)
+}
+
+fn write_players(players: &HashMap<String, PlayerIdentity>) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(players).map_err(|e| e.to_string())?;
+    fs::write(&config().files.jogadores, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Every message runs in its own task, so registry read-modify-write cycles take turns.
+/// Also held while the team files and the name history are updated alongside the registry.
+static PLAYERS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads the registry, applies `change` and writes it back if anything changed, all while
+/// holding the registry lock so a concurrent update is never overwritten by a stale copy.
+fn update_players<T>(change: impl FnOnce(&mut HashMap<String, PlayerIdentity>) -> Result<T, String>) -> Result<T, String> {
+    let _guard = PLAYERS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut players = read_players()?;
+    let before = serde_json::to_string(&players).map_err(|e| e.to_string())?;
+    let result = change(&mut players)?;
+    if serde_json::to_string(&players).map_err(|e| e.to_string())? != before {
+        write_players(&players)?;
+    }
+    Ok(result)
+}
+
+fn display_name(user: &User) -> String {
+    match &user.last_name {
+        Some(last_name) => format!("{} {}", user.first_name, last_name),
+        None => user.first_name.clone(),
+    }
+}
+
+/// The name to show for a player id, falling back to the key itself for
+/// entries that still use a first name.
+pub fn player_name(players: &HashMap<String, PlayerIdentity>, key: &str) -> String {
+    match players.get(key) {
+        Some(player) if !player.display_name.is_empty() => player.display_name.clone(),
+        Some(player) => player.first_name.clone(),
+        None => key.to_string(),
+    }
+}
+
+/// Links the chat's crew entry of a user to their id and keeps its names current.
+/// Entries not linked yet are matched by username.
+fn link_crew_member(chat_id: i64, user: &User, old_username: &str) -> Result<(), String> {
+    let username = user.username.clone().unwrap_or_default();
+    let matches_username = |name: &str| [username.as_str(), old_username].iter().any(|u| !u.is_empty() && name.eq_ignore_ascii_case(u));
+    update_crew(chat_id, |crew| {
+        let member = crew.all_members_mut().find(|m| match m.user_id {
+            Some(id) => id == user.id,
+            None => matches_username(&m.username),
+        });
+        if let Some(member) = member {
+            member.user_id = Some(user.id);
+            member.username = username.clone();
+            member.first_name = user.first_name.clone();
+        }
+        Ok(())
+    })
+}
+
+/// Adds a Telegram user to the registry the first time they use the bot, and
+/// links the team and crew entries that share their username to their id.
+pub fn register_player(chat_id: i64, user: &User) -> Result<(), String> {
+    update_players(|players| {
+        if players.contains_key(&user.id.to_string()) {
+            return Ok(());
+        }
+        let username = user.username.clone().unwrap_or_default();
+        let mut player = PlayerIdentity {
+            username: username.clone(),
+            first_name: user.first_name.clone(),
+            display_name: display_name(user),
+            nick: String::new(),
+            team: String::new(),
+            log_opt_out: false,
+        };
+
+        if !username.is_empty() {
+            for team_name in TEAMS {
+                let Ok(mut team) = read_team(team_name) else {
+                    continue;
+                };
+                if let Some(entry) = team.iter_mut().find(|p| p.user.trim_start_matches('@').eq_ignore_ascii_case(&username)) {
+                    entry.user_id = Some(user.id);
+                    player.team = team_name.to_string();
+                    write_team(team_name, &team)?;
+                }
+            }
+        }
+
+        players.insert(user.id.to_string(), player);
+        Ok(())
+    })?;
+    link_crew_member(chat_id, user, "")
+}
+
+pub fn read_name_history() -> Result<Vec<NameChange>, String> {
+    let data = match fs::read_to_string(&config().files.nomes_historico) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.to_string()),
+    };
+    serde_json::from_str(&data).map_err(|e| e.to_string())
+}
+
+fn write_name_history(history: &[NameChange]) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(history).map_err(|e| e.to_string())?;
+    fs::write(&config().files.nomes_historico, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Refreshes the stored username and first name of a registered player when they
+/// rename on Telegram, in the registry and in the team and crew files linked to them.
+/// Each chat's crew catches up the next time the player talks there.
+pub fn observe_user(chat_id: i64, user: &User) -> Result<(), String> {
+    let username = user.username.clone().unwrap_or_default();
+    // The previous username of a player who renamed, empty when nothing changed.
+    let old_username = update_players(|players| {
+        let Some(player) = players.get_mut(&user.id.to_string()) else {
+            return Ok(None);
+        };
+        if player.username == username && player.first_name == user.first_name {
+            return Ok(Some(String::new()));
+        }
+
+        let change = NameChange {
+            user_id: user.id,
+            date: Local::now().to_rfc3339(),
+            old_username: player.username.clone(),
+            new_username: username.clone(),
+            old_first_name: player.first_name.clone(),
+            new_first_name: user.first_name.clone(),
+        };
+        println!(
+            "Player {} renamed: @{} ({}) -> @{} ({})",
+            user.id, change.old_username, change.old_first_name, change.new_username, change.new_first_name
+        );
+        let is_old_username = |name: &str| !change.old_username.is_empty() && name.trim_start_matches('@').eq_ignore_ascii_case(&change.old_username);
+
+        for team_name in TEAMS {
+            let Ok(mut team) = read_team(team_name) else {
+                continue;
+            };
+            let mut changed = false;
+            for entry in team.iter_mut().filter(|p| p.user_id == Some(user.id) || (p.user_id.is_none() && is_old_username(&p.user))) {
+                entry.user_id = Some(user.id);
+                if !username.is_empty() {
+                    entry.user = format!("@{}", username);
+                }
+                changed = true;
+            }
+            if changed {
+                write_team(team_name, &team)?;
+            }
+        }
+
+        player.username = username.clone();
+        player.first_name = user.first_name.clone();
+        player.display_name = display_name(user);
+
+        let old_username = change.old_username.clone();
+        let mut history = read_name_history()?;
+        history.push(change);
+        write_name_history(&history)?;
+        Ok(Some(old_username))
+    })?;
+    match old_username {
+        Some(old_username) => link_crew_member(chat_id, user, &old_username),
+        None => Ok(()),
+    }
+}
+
+/// Lists the recorded renames of a player, oldest first.
+pub fn render_name_history(target: &Target) -> Result<String, String> {
+    let Some(user_id) = target.user_id else {
+        return Ok(format!("{} ainda não está no registro de jogadores.", target.label));
+    };
+    let changes: Vec<NameChange> = read_name_history()?.into_iter().filter(|c| c.user_id == user_id).collect();
+    if changes.is_empty() {
+        return Ok(format!("{} nunca mudou de nome.", target.label));
+    }
+    let mut response = format!("📛 Nomes de {}:\n\n", target.label);
+    for change in changes {
+        let date = DateTime::parse_from_rfc3339(&change.date).map(|d| d.format("%d/%m/%Y").to_string()).unwrap_or(change.date);
+        response.push_str(&format!(
+            "{}: @{} ({}) → @{} ({})\n",
+            date, change.old_username, change.old_first_name, change.new_username, change.new_first_name
+        ));
+    }
+    Ok(response)
+}
+
+pub fn is_log_opt_out(user_id: u64) -> bool {
+    read_players().is_ok_and(|players| players.get(&user_id.to_string()).is_some_and(|p| p.log_opt_out))
+}
+
+pub fn set_log_opt_out(chat_id: i64, user: &User, opt_out: bool) -> Result<(), String> {
+    register_player(chat_id, user)?;
+    update_players(|players| {
+        if let Some(player) = players.get_mut(&user.id.to_string()) {
+            player.log_opt_out = opt_out;
+        }
+        Ok(())
+    })
+}
+
+pub fn set_nick(chat_id: i64, user: &User, nick: &str) -> Result<(), String> {
+    register_player(chat_id, user)?;
+    update_players(|players| {
+        if let Some(player) = players.get_mut(&user.id.to_string()) {
+            player.nick = nick.to_string();
+        }
+        Ok(())
+    })
+}
+
+fn check_name(report: &mut Vec<String>, players: &HashMap<String, PlayerIdentity>, file: &str, key: &str) {
+    if players.contains_key(key) {
+        return;
+    }
+    if !players.values().any(|p| p.answers_to(key)) {
+        report.push(format!("{}: '{}' não corresponde a nenhum jogador registrado", file, key));
+    }
+}
+
+/// Cross-checks every data file against the registry and lists orphans
+/// (entries nobody is registered as) and mismatches (linked entries that disagree).
+pub fn check_consistency(chat_id: i64) -> Result<Vec<String>, String> {
+    let players = read_players()?;
+    let mut report = Vec::new();
+
+    for name in read_tickets()?.keys() {
+        check_name(&mut report, &players, "tickets.json", name);
+    }
+    for name in read_receitas()?.keys() {
+        check_name(&mut report, &players, "receitas.json", name);
+    }
+    for name in read_pecas()?.keys() {
+        check_name(&mut report, &players, "pecas.json", name);
+    }
+    for key in read_claims(chat_id)?.keys() {
+        check_name(&mut report, &players, "claims.json", key);
+    }
+    for id in read_inventories()?.keys() {
+        if !players.contains_key(id) {
+            report.push(format!("inventarios.json: id {} não está em jogadores.json", id));
+        }
+    }
+
+    for team_name in TEAMS {
+        let file = format!("{}.json", team_name);
+        let team = match read_team(team_name) {
+            Ok(team) => team,
+            Err(err) => {
+                report.push(format!("{}: {}", file, err));
+                continue;
+            }
+        };
+        for entry in &team {
+            let linked = entry.user_id.and_then(|id| players.get(&id.to_string()));
+            match linked {
+                Some(player) => {
+                    if !player.answers_to(&entry.user) {
+                        report.push(format!("{}: {} está ligado a @{}, mas o arquivo diz {}", file, entry.name, player.username, entry.user));
+                    }
+                    if player.team != team_name {
+                        report.push(format!("{}: {} está registrado no time '{}'", file, entry.name, player.team));
+                    }
+                }
+                None if entry.user_id.is_some() => report.push(format!("{}: {} aponta para um id que não está em jogadores.json", file, entry.name)),
+                None => check_name(&mut report, &players, &file, &entry.user),
+            }
+        }
+    }
+
+    match read_crew(chat_id) {
+        Ok(crew) => {
+            for member in crew.all_members() {
+                match member.user_id.and_then(|id| players.get(&id.to_string())) {
+                    Some(player) if !player.username.eq_ignore_ascii_case(&member.username) => {
+                        report.push(format!("tripulantes.json: @{} está ligado a @{}", member.username, player.username))
+                    }
+                    Some(_) => {}
+                    None => check_name(&mut report, &players, "tripulantes.json", &member.username),
+                }
+            }
+        }
+        Err(err) => report.push(format!("tripulantes.json: {}", err)),
+    }
+
+    for (id, player) in &players {
+        if !player.team.is_empty() && !TEAMS.contains(&player.team.as_str()) {
+            report.push(format!("jogadores.json: {} ({}) está no time desconhecido '{}'", player.first_name, id, player.team));
+        }
+    }
+    Ok(report)
+}
+
+fn target_for(user_id: Option<u64>, player: Option<&PlayerIdentity>, fallback_name: &str) -> Target {
+    let mut names = vec![fallback_name.to_string()];
+    if let Some(player) = player {
+        names = player.names().cloned().collect();
+    }
+    Target {
+        user_id,
+        label: names.first().cloned().unwrap_or_else(|| fallback_name.to_string()),
+        names,
+    }
+}
+
+/// Resolves the player a command refers to: the replied-to user, an `@username`,
+/// a first name or nick, or the caller when nothing is given.
+pub fn resolve_target(message: &Message, arg: &str) -> Result<Target, String> {
+    if arg.is_empty() {
+        let players = read_players()?;
+        let user = message
+            .reply_to_message
+            .as_ref()
+            .and_then(|reply| reply.from.as_deref())
+            .or(message.from.as_deref())
+            .ok_or("Não sei quem você é.")?;
+        return Ok(target_for(Some(user.id), players.get(&user.id.to_string()), &user.first_name));
+    }
+    resolve_name(message.chat.id, arg)
+}
+
+/// Resolves an `@username`, first name or nick as written in a chat.
+pub fn resolve_name(chat_id: i64, arg: &str) -> Result<Target, String> {
+    let players = read_players()?;
+
+    if let Some(username) = arg.strip_prefix('@') {
+        return players
+            .iter()
+            .find(|(_, p)| p.username.eq_ignore_ascii_case(username))
+            .map(|(id, p)| target_for(id.parse().ok(), Some(p), &p.first_name))
+            .or_else(|| {
+                // Crew members who never talked to the bot are still listed in tripulantes.json.
+                let crew = read_crew(chat_id).ok()?;
+                let member = crew.all_members().find(|m| m.username.eq_ignore_ascii_case(username))?;
+                Some(Target { user_id: None, label: member.first_name.clone(), names: vec![member.first_name.clone(), member.username.clone()] })
+            })
+            .ok_or_else(|| format!("Não conheço nenhum tripulante com o usuário @{}.", username));
+    }
+
+    let known = players.iter().find(|(_, p)| p.answers_to(arg));
+    Ok(match known {
+        Some((id, player)) => target_for(id.parse().ok(), Some(player), arg),
+        None => Target { user_id: None, label: arg.to_string(), names: vec![arg.to_string()] },
+    })
+}
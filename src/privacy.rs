@@ -0,0 +1,90 @@
+use crate::chatlog::{chats_with, entries_of, forget_user, redact_user, LogEntry};
+use crate::context::reset_summary;
+use crate::inventory::{read_inventories, read_pending, PlayerInventory};
+use crate::memory::forget_turns;
+use crate::players::{read_name_history, read_players, NameChange, PlayerIdentity};
+use crate::quota::{read_usage, UserUsage};
+use crate::{read_claims, read_crew, read_team, Claim, CrewMember, Player, TEAMS};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// What one chat keeps about a user.
+#[derive(Serialize, Debug)]
+struct ChatRecord {
+    chat_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claim: Option<Claim>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crew: Option<CrewMember>,
+}
+
+/// Everything stored about a user, as sent by `/exportar`.
+#[derive(Serialize, Debug)]
+struct UserExport {
+    user_id: u64,
+    player: Option<PlayerIdentity>,
+    name_history: Vec<NameChange>,
+    inventory: Option<PlayerInventory>,
+    pending_inventory: Option<PlayerInventory>,
+    usage_today: Option<UserUsage>,
+    teams: HashMap<String, Player>,
+    chats: Vec<ChatRecord>,
+    messages: Vec<LogEntry>,
+}
+
+/// Collects what the data files hold about `user_id` into pretty-printed JSON. Per-chat
+/// data (claims, crew, messages) only comes from `chats`, the ones the caller administers.
+pub fn export_user(user_id: u64, chats: &[i64]) -> Result<String, String> {
+    let key = user_id.to_string();
+    let mut teams = HashMap::new();
+    for team_name in TEAMS {
+        let Ok(team) = read_team(team_name) else {
+            continue;
+        };
+        if let Some(player) = team.into_iter().find(|p| p.user_id == Some(user_id)) {
+            teams.insert(team_name.to_string(), player);
+        }
+    }
+    let mut records = Vec::new();
+    for &chat_id in chats {
+        let claim = read_claims(chat_id)?.remove(&key);
+        let crew = read_crew(chat_id)?.all_members().find(|m| m.user_id == Some(user_id)).cloned();
+        if claim.is_some() || crew.is_some() {
+            records.push(ChatRecord { chat_id, claim, crew });
+        }
+    }
+    let export = UserExport {
+        user_id,
+        player: read_players()?.remove(&key),
+        name_history: read_name_history()?.into_iter().filter(|change| change.user_id == user_id).collect(),
+        inventory: read_inventories()?.remove(&key),
+        pending_inventory: read_pending()?.remove(&key),
+        usage_today: read_usage()?.users.remove(&key),
+        teams,
+        chats: records,
+        messages: entries_of(user_id, chats),
+    };
+    serde_json::to_string_pretty(&export).map_err(|e| e.to_string())
+}
+
+/// Rewrites the chat logs with `rewrite`, then drops what was built from the user's messages:
+/// the summaries of the chats they wrote in and their questions in Will's conversations.
+fn scrub_user(user_id: u64, rewrite: fn(u64) -> Result<usize, String>) -> Result<usize, String> {
+    let chats = chats_with(user_id);
+    let touched = rewrite(user_id)?;
+    for chat_id in chats {
+        reset_summary(chat_id)?;
+    }
+    forget_turns(user_id)?;
+    Ok(touched)
+}
+
+/// `/privacidade off`: hides the user's logged messages and everything derived from them.
+pub fn hide_user(user_id: u64) -> Result<usize, String> {
+    scrub_user(user_id, redact_user)
+}
+
+/// `/esquecer`: deletes the user's logged messages and everything derived from them.
+pub fn erase_user(user_id: u64) -> Result<usize, String> {
+    scrub_user(user_id, forget_user)
+}
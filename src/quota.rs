@@ -0,0 +1,200 @@
+use crate::config::config;
+use chrono::Local;
+use gemini_rust::UsageMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+/// Each user can ask three questions in a burst, then one every 20 seconds.
+const USER_BUCKET: BucketConfig = BucketConfig { capacity: 3.0, refill_per_sec: 1.0 / 20.0 };
+/// The whole chat shares ten questions in a burst, then one every 6 seconds.
+const CHAT_BUCKET: BucketConfig = BucketConfig { capacity: 10.0, refill_per_sec: 1.0 / 6.0 };
+/// Used when `DAILY_TOKEN_BUDGET` is not set.
+const DEFAULT_DAILY_TOKEN_BUDGET: u64 = 2_000_000;
+/// Gemini 2.5 Flash list prices in USD per million tokens; thinking is billed as output.
+const INPUT_PRICE_PER_MILLION: f64 = 0.30;
+const OUTPUT_PRICE_PER_MILLION: f64 = 2.50;
+
+struct BucketConfig {
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+static BUCKETS: LazyLock<Mutex<HashMap<String, Bucket>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn refill<'a>(buckets: &'a mut HashMap<String, Bucket>, key: String, config: &BucketConfig) -> &'a mut Bucket {
+    let now = Instant::now();
+    let bucket = buckets.entry(key).or_insert(Bucket { tokens: config.capacity, last_refill: now });
+    let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * config.refill_per_sec).min(config.capacity);
+    bucket.last_refill = now;
+    bucket
+}
+
+/// Takes one token from both the user's and the chat's bucket, or neither.
+pub fn check_rate_limit(chat_id: i64, user_id: u64) -> bool {
+    let mut buckets = BUCKETS.lock().unwrap();
+    let user_key = format!("user:{}", user_id);
+    let chat_key = format!("chat:{}", chat_id);
+    let user_ok = refill(&mut buckets, user_key.clone(), &USER_BUCKET).tokens >= 1.0;
+    let chat_ok = refill(&mut buckets, chat_key.clone(), &CHAT_BUCKET).tokens >= 1.0;
+    if !(user_ok && chat_ok) {
+        return false;
+    }
+    for key in [user_key, chat_key] {
+        if let Some(bucket) = buckets.get_mut(&key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TokenUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn add(&mut self, metadata: &UsageMetadata) {
+        self.prompt_tokens += metadata.prompt_token_count.max(0) as u64;
+        self.output_tokens += metadata.candidates_token_count.unwrap_or(0).max(0) as u64;
+        self.output_tokens += metadata.thoughts_token_count.unwrap_or(0).max(0) as u64;
+    }
+
+    pub fn merge(&mut self, other: &TokenUsage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.output_tokens
+    }
+
+    pub fn cost_usd(&self) -> f64 {
+        (self.prompt_tokens as f64 * INPUT_PRICE_PER_MILLION + self.output_tokens as f64 * OUTPUT_PRICE_PER_MILLION) / 1_000_000.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UserUsage {
+    pub name: String,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DailyUsage {
+    pub date: String,
+    pub total: TokenUsage,
+    #[serde(default)]
+    pub chats: HashMap<String, TokenUsage>,
+    #[serde(default)]
+    pub users: HashMap<String, UserUsage>,
+}
+
+pub fn read_usage() -> Result<DailyUsage, String> {
+    let data = fs::read_to_string(&config().files.uso).map_err(|e| e.to_string())?;
+    let usage: DailyUsage = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    Ok(usage)
+}
+
+fn write_usage(usage: &DailyUsage) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(usage).map_err(|e| e.to_string())?;
+    fs::write(&config().files.uso, data).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Today's usage; the counters start over on the first call of a new day.
+fn today_usage() -> Result<DailyUsage, String> {
+    let today = Local::now().date_naive().to_string();
+    let usage = read_usage()?;
+    if usage.date == today {
+        Ok(usage)
+    } else {
+        Ok(DailyUsage { date: today, ..Default::default() })
+    }
+}
+
+fn daily_token_budget() -> u64 {
+    env::var("DAILY_TOKEN_BUDGET")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DAILY_TOKEN_BUDGET)
+}
+
+pub fn daily_budget_exceeded() -> bool {
+    match today_usage() {
+        Ok(usage) => usage.total.total_tokens() >= daily_token_budget(),
+        Err(err) => {
+            println!("Failed to read usage: {}", err);
+            false
+        }
+    }
+}
+
+/// Every `/will` runs in its own task, so usage read-modify-write cycles take turns.
+static USAGE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads today's usage, applies `change` and writes it back while holding the usage lock,
+/// so overlapping questions never overwrite each other's counts.
+fn update_usage(change: impl FnOnce(&mut DailyUsage)) -> Result<(), String> {
+    let _guard = USAGE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut daily = today_usage()?;
+    change(&mut daily);
+    write_usage(&daily)
+}
+
+pub fn record_usage(chat_id: i64, user_id: u64, user_name: &str, usage: &TokenUsage) -> Result<(), String> {
+    update_usage(|daily| {
+        daily.total.merge(usage);
+        daily.chats.entry(chat_id.to_string()).or_default().merge(usage);
+        let user = daily.users.entry(user_id.to_string()).or_default();
+        user.name = user_name.to_string();
+        user.usage.merge(usage);
+    })
+}
+
+pub fn usage_report() -> Result<String, String> {
+    let daily = today_usage()?;
+    let budget = daily_token_budget();
+    let mut response = format!(
+        "📊 Uso do Will em {}:\n\nPerguntas: {}\nTokens: {} de {} ({} entrada, {} saída)\nCusto estimado: US$ {:.4}\n",
+        daily.date,
+        daily.total.requests,
+        daily.total.total_tokens(),
+        budget,
+        daily.total.prompt_tokens,
+        daily.total.output_tokens,
+        daily.total.cost_usd()
+    );
+
+    let mut users: Vec<&UserUsage> = daily.users.values().collect();
+    users.sort_by_key(|u| std::cmp::Reverse(u.usage.total_tokens()));
+    if !users.is_empty() {
+        response.push_str("\nPor tripulante:\n");
+        for user in users {
+            response.push_str(&format!("- {}: {} perguntas, {} tokens\n", user.name, user.usage.requests, user.usage.total_tokens()));
+        }
+    }
+
+    let mut chats: Vec<(&String, &TokenUsage)> = daily.chats.iter().collect();
+    chats.sort_by_key(|(_, u)| std::cmp::Reverse(u.total_tokens()));
+    if chats.len() > 1 {
+        response.push_str("\nPor chat:\n");
+        for (chat_id, usage) in chats {
+            response.push_str(&format!("- {}: {} perguntas, {} tokens\n", chat_id, usage.requests, usage.total_tokens()));
+        }
+    }
+    Ok(response)
+}
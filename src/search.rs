@@ -0,0 +1,114 @@
+use crate::chatlog::{read_log, LogEntry, REDACTED_TEXT};
+use crate::context::fold;
+use std::collections::{HashMap, HashSet};
+
+/// Most messages `/buscar` lists.
+const MAX_RESULTS: usize = 10;
+/// Longer messages are cut in the results.
+const MAX_TEXT_CHARS: usize = 200;
+
+/// An inverted index of a chat's log: every accent-folded word points to the
+/// entries that contain it.
+pub struct SearchIndex {
+    entries: Vec<LogEntry>,
+    words: HashMap<String, Vec<usize>>,
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+impl SearchIndex {
+    /// Indexes the retained log of a chat. Commands and redacted messages are left out,
+    /// so `/buscar` never finds itself.
+    pub fn build(chat_id: i64) -> SearchIndex {
+        let entries: Vec<LogEntry> =
+            read_log(chat_id).into_iter().filter(|entry| entry.text != REDACTED_TEXT && !entry.text.starts_with('/')).collect();
+        let mut words_index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, entry) in entries.iter().enumerate() {
+            let folded = fold(&entry.text);
+            let unique: HashSet<&str> = words(&folded).collect();
+            for word in unique {
+                words_index.entry(word.to_string()).or_default().push(position);
+            }
+        }
+        SearchIndex { entries, words: words_index }
+    }
+
+    /// Entries containing every word of the query (as a word prefix, so `jog` finds `jogo`),
+    /// most recent first.
+    pub fn search(&self, query: &str) -> Vec<&LogEntry> {
+        let query = fold(query);
+        let mut matches: Option<HashSet<usize>> = None;
+        for term in words(&query) {
+            let found: HashSet<usize> =
+                self.words.iter().filter(|(word, _)| word.starts_with(term)).flat_map(|(_, positions)| positions.iter().copied()).collect();
+            matches = Some(match matches {
+                Some(previous) => previous.intersection(&found).copied().collect(),
+                None => found,
+            });
+        }
+        let mut positions: Vec<usize> = matches.unwrap_or_default().into_iter().collect();
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        positions.into_iter().map(|position| &self.entries[position]).collect()
+    }
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= MAX_TEXT_CHARS {
+        return text.to_string();
+    }
+    format!("{}…", text.chars().take(MAX_TEXT_CHARS).collect::<String>())
+}
+
+/// The reply to `/buscar`: the latest matching messages with author and date.
+pub fn render_search(chat_id: i64, query: &str) -> String {
+    if query.trim().is_empty() {
+        return "Use /buscar {termo}.".to_string();
+    }
+    let index = SearchIndex::build(chat_id);
+    let results = index.search(query);
+    if results.is_empty() {
+        return format!("🔎 Nada encontrado para \"{}\".", query.trim());
+    }
+    let mut response = format!("🔎 {} mensagens com \"{}\"", results.len(), query.trim());
+    if results.len() > MAX_RESULTS {
+        response.push_str(&format!(" (mostrando as {} mais recentes)", MAX_RESULTS));
+    }
+    response.push_str(":\n\n");
+    for entry in results.into_iter().take(MAX_RESULTS) {
+        let time = entry.time().map(|t| t.format("%d/%m %H:%M").to_string()).unwrap_or_default();
+        response.push_str(&format!("[{}] {}: {}\n", time, entry.first_name, shorten(&entry.text)));
+    }
+    response
+}
+
+/// The reply to `/quem disse`: who wrote the term, how often and when they last did.
+pub fn render_who_said(chat_id: i64, query: &str) -> String {
+    if query.trim().is_empty() {
+        return "Use /quem disse {termo}.".to_string();
+    }
+    let index = SearchIndex::build(chat_id);
+    // Results come newest first, so the first entry seen for an author is their latest.
+    let mut authors: Vec<(&LogEntry, usize)> = Vec::new();
+    for entry in index.search(query) {
+        let author = |other: &LogEntry| match (entry.user_id, other.user_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => entry.first_name == other.first_name,
+        };
+        match authors.iter_mut().find(|(latest, _)| author(latest)) {
+            Some((_, count)) => *count += 1,
+            None => authors.push((entry, 1)),
+        }
+    }
+    if authors.is_empty() {
+        return format!("🗣️ Ninguém disse \"{}\" por aqui.", query.trim());
+    }
+    authors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let mut response = format!("🗣️ Quem disse \"{}\":\n\n", query.trim());
+    for (latest, count) in authors {
+        let date = latest.time().map(|t| t.format("%d/%m").to_string()).unwrap_or_default();
+        response.push_str(&format!("{} - {}x, a última em {}: {}\n", latest.first_name, count, date, shorten(&latest.text)));
+    }
+    response
+}
@@ -0,0 +1,111 @@
+use crate::chatlog::{read_log, LogEntry};
+use crate::{read_crew, update_crew, CrewMember, Rank};
+use chrono::{Local, NaiveDate, Timelike};
+use frankenstein::types::User;
+
+/// Members silent for longer than this are flagged unless `/estatisticas` gets another number.
+pub const DEFAULT_INACTIVE_DAYS: i64 = 7;
+/// Busiest hours listed for the whole chat.
+const TOP_HOURS: usize = 3;
+
+/// Records that a crew member was active today. Runs on every message, including
+/// stickers and photos that never reach the chat log; the crew file is written once a day at most.
+pub fn mark_seen(chat_id: i64, user: &User) -> Result<(), String> {
+    // Checked before taking the crew lock, so most messages never wait for it.
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    if read_crew(chat_id)?.all_members().any(|m| m.user_id == Some(user.id) && m.last_seen.as_deref() == Some(today.as_str())) {
+        return Ok(());
+    }
+    update_crew(chat_id, |crew| {
+        if let Some(member) = crew.find_member_mut(user) {
+            member.last_seen = Some(today);
+        }
+        Ok(())
+    })
+}
+
+/// Activity of one crew member in the retained log.
+struct MemberActivity {
+    messages: usize,
+    hours: [usize; 24],
+    last_seen: Option<NaiveDate>,
+}
+
+fn is_author(member: &CrewMember, entry: &LogEntry) -> bool {
+    match (member.user_id, entry.user_id) {
+        (Some(member_id), Some(author_id)) => member_id == author_id,
+        _ => entry.username.as_deref().is_some_and(|username| !member.username.is_empty() && username.eq_ignore_ascii_case(&member.username)),
+    }
+}
+
+fn activity(member: &CrewMember, entries: &[LogEntry]) -> MemberActivity {
+    let mut activity = MemberActivity {
+        messages: 0,
+        hours: [0; 24],
+        last_seen: member.last_seen.as_deref().and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()),
+    };
+    for entry in entries.iter().filter(|entry| is_author(member, entry)) {
+        activity.messages += 1;
+        if let Some(time) = entry.time() {
+            activity.hours[time.hour() as usize] += 1;
+            activity.last_seen = activity.last_seen.max(Some(time.date_naive()));
+        }
+    }
+    activity
+}
+
+fn peak_hour(hours: &[usize; 24]) -> Option<usize> {
+    (0..24).filter(|hour| hours[*hour] > 0).max_by_key(|hour| (hours[*hour], std::cmp::Reverse(*hour)))
+}
+
+/// The reply to `/estatisticas`: messages per active crew member by rank, the busiest
+/// hours of the chat and who has not been seen in `inactive_days`.
+pub fn render_statistics(chat_id: i64, inactive_days: i64) -> Result<String, String> {
+    let mut crew = read_crew(chat_id)?;
+    let entries = read_log(chat_id);
+    let today = Local::now().date_naive();
+
+    let mut response = match entries.first().and_then(LogEntry::time) {
+        Some(since) => format!("📊 Atividade da tripulação desde {}:\n", since.format("%d/%m")),
+        None => "📊 Atividade da tripulação (o histórico do chat está vazio):\n".to_string(),
+    };
+
+    let mut inactive = Vec::new();
+    for rank in Rank::ALL.into_iter().rev() {
+        let members: Vec<&CrewMember> = crew.rank_members(rank).iter().filter(|m| m.is_crewmember).collect();
+        if members.is_empty() {
+            continue;
+        }
+        response.push_str(&format!("\n{}:\n", rank.title()));
+        for member in members {
+            let activity = activity(member, &entries);
+            let last_seen = match activity.last_seen {
+                Some(date) => date.format("%d/%m").to_string(),
+                None => "nunca".to_string(),
+            };
+            let peak = peak_hour(&activity.hours).map(|hour| format!(", mais ativo às {}h", hour)).unwrap_or_default();
+            response.push_str(&format!("- {} (@{}): {} mensagens{}, visto em {}\n", member.first_name, member.username, activity.messages, peak, last_seen));
+            if activity.last_seen.is_none_or(|date| (today - date).num_days() > inactive_days) {
+                inactive.push(format!("- {} ({}), visto em {}", member.first_name, rank.title(), last_seen));
+            }
+        }
+    }
+
+    let mut hours = [0usize; 24];
+    for time in entries.iter().filter_map(LogEntry::time) {
+        hours[time.hour() as usize] += 1;
+    }
+    let mut busiest: Vec<usize> = (0..24).filter(|hour| hours[*hour] > 0).collect();
+    busiest.sort_by_key(|hour| std::cmp::Reverse(hours[*hour]));
+    if !busiest.is_empty() {
+        let busiest = busiest.iter().take(TOP_HOURS).map(|hour| format!("{}h ({})", hour, hours[*hour])).collect::<Vec<String>>();
+        response.push_str(&format!("\n🕘 Horários mais movimentados: {}\n", busiest.join(", ")));
+    }
+
+    if inactive.is_empty() {
+        response.push_str(&format!("\n✅ Todos falaram nos últimos {} dias.", inactive_days));
+    } else {
+        response.push_str(&format!("\n⚠️ Sem falar há mais de {} dias:\n{}", inactive_days, inactive.join("\n")));
+    }
+    Ok(response)
+}
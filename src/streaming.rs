@@ -0,0 +1,114 @@
+use frankenstein::client_reqwest::Bot;
+use frankenstein::methods::{EditMessageTextParams, SendMessageParams};
+use frankenstein::types::ReplyParameters;
+use frankenstein::AsyncTelegramApi;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+/// Telegram rate-limits edits, so partial answers are pushed at most this often.
+const EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+/// Telegram rejects message texts longer than this many characters.
+const MAX_MESSAGE_CHARS: usize = 4096;
+
+pub const PLACEHOLDER_TEXT: &str = "⚓ ...";
+
+pub fn truncate(text: &str) -> String {
+    text.chars().take(MAX_MESSAGE_CHARS).collect()
+}
+
+/// Splits a long answer into messages Telegram accepts, breaking at the last line break
+/// (or else the last space) that fits, so words and paragraphs stay whole where possible.
+fn split_message(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+    while rest.chars().count() > MAX_MESSAGE_CHARS {
+        let limit = rest.char_indices().nth(MAX_MESSAGE_CHARS).map_or(rest.len(), |(index, _)| index);
+        let head = &rest[..limit];
+        let cut = head.rfind('\n').or_else(|| head.rfind(' ')).filter(|index| *index > 0).unwrap_or(limit);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() || parts.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+/// Edits the placeholder with the latest partial answer until the sender is dropped.
+pub fn spawn_progress_editor(bot: Bot, chat_id: i64, message_id: i32, mut progress: watch::Receiver<String>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while progress.changed().await.is_ok() {
+            let text = progress.borrow_and_update().clone();
+            if !text.trim().is_empty() {
+                let edit_params = EditMessageTextParams::builder()
+                    .chat_id(chat_id)
+                    .message_id(message_id)
+                    .text(truncate(&text))
+                    .build();
+                if let Err(err) = bot.edit_message_text(&edit_params).await {
+                    println!("Failed to edit message: {:?}", err);
+                }
+            }
+            sleep(EDIT_INTERVAL).await;
+        }
+    })
+}
+
+/// Puts the final text in the placeholder, or sends it as a new reply when there is
+/// no placeholder or editing fails. Answers too long for one message continue in
+/// follow-up replies. Returns the ids of the messages holding the answer.
+pub async fn finish_reply(bot: &Bot, chat_id: i64, reply_to: i32, placeholder: Option<i32>, text: &str) -> Vec<i32> {
+    let parts = split_message(text);
+    let mut message_ids = Vec::new();
+    if let Some(message_id) = placeholder {
+        let edit_params = EditMessageTextParams::builder()
+            .chat_id(chat_id)
+            .message_id(message_id)
+            .text(parts[0].clone())
+            .build();
+        match bot.edit_message_text(&edit_params).await {
+            Ok(_) => message_ids.push(message_id),
+            // The last progress edit already showed the full answer.
+            Err(frankenstein::Error::Api(err)) if err.description.contains("message is not modified") => message_ids.push(message_id),
+            Err(err) => println!("Failed to edit message: {:?}", err),
+        }
+    }
+
+    // The first part is only sent when the placeholder did not take it.
+    for part in parts.into_iter().skip(message_ids.len()) {
+        let send_message_params = SendMessageParams::builder()
+            .chat_id(chat_id)
+            .text(part)
+            .reply_parameters(ReplyParameters::builder().message_id(message_ids.last().copied().unwrap_or(reply_to)).build())
+            .build();
+        match bot.send_message(&send_message_params).await {
+            Ok(sent) => message_ids.push(sent.result.message_id),
+            Err(err) => {
+                println!("Failed to send message: {:?}", err);
+                break;
+            }
+        }
+    }
+    message_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_answers_are_split_without_losing_text() {
+        let paragraph = "palavra ".repeat(300);
+        let text = format!("{}\n{}\n{}", paragraph, paragraph, paragraph);
+        let parts = split_message(&text);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.chars().count() <= MAX_MESSAGE_CHARS));
+        assert_eq!(parts.join(" ").split_whitespace().count(), text.split_whitespace().count());
+        assert!(parts[0].ends_with("palavra"));
+
+        let unbroken = "ã".repeat(MAX_MESSAGE_CHARS + 10);
+        assert_eq!(split_message(&unbroken).iter().map(|part| part.chars().count()).collect::<Vec<usize>>(), vec![MAX_MESSAGE_CHARS, 10]);
+        assert_eq!(split_message("curta"), vec!["curta".to_string()]);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::chats::settings_for_chat;
+use crate::inventory::{find_inventory, render_recipes};
+use crate::players::{player_name, read_players, resolve_name};
+use crate::{find_next_game, read_calendar, read_claims, read_scoreboard, Claim, TEAMS};
+use chrono::Local;
+use gemini_rust::{Content, FunctionCall, FunctionDeclaration, FunctionParameters, Message, Part, PropertyDetails, Role, Tool};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Declares the bot commands Will may call to check facts before answering.
+pub fn will_tools() -> Tool {
+    Tool::with_functions(vec![
+        FunctionDeclaration::new(
+            "proximo_jogo",
+            "Retorna o próximo jogo do time deste chat com data, horário, fase e adversários.",
+            FunctionParameters::object(),
+        ),
+        FunctionDeclaration::new(
+            "placar_time",
+            "Retorna a pontuação de um time, ordenada do maior para o menor.",
+            FunctionParameters::object().with_property("time", PropertyDetails::enum_type("Nome do time", TEAMS), true),
+        ),
+        FunctionDeclaration::new(
+            "tickets_jogador",
+            "Retorna os tickets, tickets VIP e o ticket selecionado de um jogador.",
+            FunctionParameters::object().with_property("nome", PropertyDetails::string("Nome, nick ou @usuário do jogador"), true),
+        ),
+        FunctionDeclaration::new(
+            "receitas_jogador",
+            "Retorna as receitas de um jogador.",
+            FunctionParameters::object().with_property("nome", PropertyDetails::string("Nome, nick ou @usuário do jogador"), true),
+        ),
+        FunctionDeclaration::new(
+            "claims_atuais",
+            "Retorna os papéis reivindicados por cada tripulante na partida atual.",
+            FunctionParameters::object(),
+        ),
+    ])
+}
+
+fn execute_tool(chat_id: i64, call: &FunctionCall) -> Result<Value, String> {
+    match call.name.as_str() {
+        "proximo_jogo" => {
+            let games = read_calendar(chat_id)?;
+            let team = settings_for_chat(chat_id)?.team;
+            Ok(json!({ "jogo": find_next_game(games, &team, Local::now().date_naive()) }))
+        }
+        "placar_time" => {
+            let team: String = call.get("time").map_err(|e| e.to_string())?;
+            Ok(json!({ "time": team, "jogadores": read_scoreboard(&team)? }))
+        }
+        // Same lookup and rendering as /tickets and /receitas, so Will never disagrees with them.
+        "tickets_jogador" => {
+            let name: String = call.get("nome").map_err(|e| e.to_string())?;
+            let target = resolve_name(chat_id, &name)?;
+            let tickets = find_inventory(&target)?
+                .filter(|inventory| !inventory.tickets.items.is_empty() || inventory.tickets.selected_ticket.is_some())
+                .map(|inventory| inventory.tickets.render());
+            Ok(json!({ "nome": target.label, "tickets": tickets }))
+        }
+        "receitas_jogador" => {
+            let name: String = call.get("nome").map_err(|e| e.to_string())?;
+            let target = resolve_name(chat_id, &name)?;
+            let recipes = find_inventory(&target)?.filter(|inventory| !inventory.recipes.is_empty()).map(|inventory| render_recipes(&inventory.recipes));
+            Ok(json!({ "nome": target.label, "receitas": recipes }))
+        }
+        "claims_atuais" => {
+            let players = read_players()?;
+            let claims: HashMap<String, Claim> = read_claims(chat_id)?.into_iter().map(|(key, claim)| (player_name(&players, &key), claim)).collect();
+            Ok(json!({ "claims": claims }))
+        }
+        other => Err(format!("Ferramenta desconhecida: {}", other)),
+    }
+}
+
+/// The model turn that requested the calls, to be replayed in the next request.
+pub fn tool_call_message(calls: &[FunctionCall]) -> Message {
+    let parts = calls.iter().map(|call| Part::FunctionCall { function_call: call.clone() }).collect();
+    Message { content: Content { parts, role: Some(Role::Model) }, role: Role::Model }
+}
+
+/// Runs every requested call and packs the results into a single user turn.
+pub fn run_tool_calls(chat_id: i64, calls: &[FunctionCall]) -> Message {
+    let mut content = Content { parts: Vec::new(), role: Some(Role::User) };
+    for call in calls {
+        println!("Tool call: {}({})", call.name, call.args);
+        let result = execute_tool(chat_id, call).unwrap_or_else(|err| json!({ "erro": err }));
+        println!("Tool result: {} -> {}", call.name, result);
+        content.parts.extend(Content::function_response_json(call.name.clone(), result).parts);
+    }
+    Message { content, role: Role::User }
+}
@@ -0,0 +1,140 @@
+use crate::chats::{known_chats, read_chat_settings};
+use crate::config::config;
+use crate::context::read_summary;
+use crate::digest::read_digest_state;
+use crate::inventory::{read_inventories, read_pecas, read_pending, read_receitas, read_tickets};
+use crate::memory::read_conversations;
+use crate::persona::{read_personas, DEFAULT_PERSONA};
+use crate::players::{read_name_history, read_players};
+use crate::quota::read_usage;
+use crate::{read_calendar, read_claims, read_crew, read_missions, read_papeis, read_team, Game, Mission, TEAMS};
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&data).map_err(|e| e.to_string())
+}
+
+fn check_calendar(problems: &mut Vec<String>, file: &str, games: &[Game]) {
+    for game in games {
+        if NaiveDate::parse_from_str(&format!("{}/2025", game.date), "%d/%m/%Y").is_err() {
+            problems.push(format!("{}: data inválida '{}' (use dd/mm)", file, game.date));
+        }
+    }
+}
+
+/// Checks the files of one chat; the calendar and missions only when the chat has its own copy.
+fn validate_chat(problems: &mut Vec<String>, chat_id: i64) {
+    let label = |file: &Path| format!("chats/{}/{}", chat_id, file.file_name().map(|n| n.to_string_lossy()).unwrap_or_default());
+    let files = &config().files;
+
+    if let Some(crew) = check(problems, &label(&files.tripulantes), read_crew(chat_id)) {
+        if crew.captain.iter().filter(|m| m.is_crewmember).count() > 1 {
+            problems.push(format!("{}: há mais de um capitão a bordo", label(&files.tripulantes)));
+        }
+        let mut usernames = HashSet::new();
+        for member in crew.all_members() {
+            if member.username.is_empty() {
+                problems.push(format!("{}: {} está sem username", label(&files.tripulantes), member.first_name));
+            } else if !usernames.insert(member.username.to_lowercase()) {
+                problems.push(format!("{}: @{} aparece mais de uma vez", label(&files.tripulantes), member.username));
+            }
+        }
+    }
+    check(problems, &label(&files.claims), read_claims(chat_id));
+    check(problems, &label(&files.resumo_chat), read_summary(chat_id));
+    check(problems, &label(&files.resumo_diario), read_digest_state(chat_id));
+    if config().chat_path(chat_id, &files.calendario).exists() {
+        if let Some(games) = check(problems, &label(&files.calendario), read_calendar(chat_id)) {
+            check_calendar(problems, &label(&files.calendario), &games);
+        }
+    }
+    if config().chat_path(chat_id, &files.missoes).exists() {
+        check(problems, &label(&files.missoes), read_missions(chat_id));
+    }
+}
+
+fn check<T>(problems: &mut Vec<String>, file: &str, result: Result<T, String>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            problems.push(format!("{}: {}", file, err));
+            None
+        }
+    }
+}
+
+/// Loads every data file and checks the rules the commands rely on, returning all
+/// problems found instead of stopping at the first one.
+pub fn validate_data() -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(papeis) = check(&mut problems, "papeis.json", read_papeis()) {
+        // `/claim` matches nicks ignoring case, so a nick shared by two roles is ambiguous.
+        let mut nicks: HashMap<String, &str> = HashMap::new();
+        for papel in &papeis {
+            for nick in &papel.nicks {
+                match nicks.insert(nick.to_lowercase(), &papel.name) {
+                    Some(other) if other != papel.name => {
+                        problems.push(format!("papeis.json: o nick '{}' aparece em {} e {}", nick, other, papel.name))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let shared_calendar = read_json::<Vec<Game>>(&config().files.calendario);
+    if let Some(games) = check(&mut problems, "calendario.json", shared_calendar) {
+        check_calendar(&mut problems, "calendario.json", &games);
+    }
+    check(&mut problems, "missoes.json", read_json::<Mission>(&config().files.missoes));
+
+    for team_name in TEAMS {
+        check(&mut problems, &format!("{}.json", team_name), read_team(team_name));
+    }
+
+    for chat_id in known_chats() {
+        validate_chat(&mut problems, chat_id);
+    }
+
+    if let Some(personas) = check(&mut problems, "personas.json", read_personas()) {
+        let ids: HashSet<&str> = personas.iter().map(|p| p.id.as_str()).collect();
+        if ids.len() != personas.len() {
+            problems.push("personas.json: há personas com o mesmo id".to_string());
+        }
+        for persona in &personas {
+            if let Some(photo) = &persona.welcome.photo {
+                if !config().data_dir.join(photo).exists() {
+                    problems.push(format!("personas.json: a foto '{}' da persona '{}' não existe", photo, persona.id));
+                }
+            }
+        }
+        if !ids.contains(DEFAULT_PERSONA) {
+            problems.push(format!("personas.json: falta a persona padrão '{}'", DEFAULT_PERSONA));
+        }
+        if let Some(settings) = check(&mut problems, "chats.json", read_chat_settings()) {
+            for (chat_id, persona_id) in settings.iter().filter_map(|(id, s)| Some((id, s.persona.as_deref()?))) {
+                if !ids.contains(persona_id) {
+                    problems.push(format!("chats.json: o chat {} usa a persona desconhecida '{}'", chat_id, persona_id));
+                }
+            }
+        }
+    }
+
+    check(&mut problems, "tickets.json", read_tickets());
+    check(&mut problems, "receitas.json", read_receitas());
+    check(&mut problems, "pecas.json", read_pecas());
+    check(&mut problems, "inventarios.json", read_inventories());
+    check(&mut problems, "inventarios_pendentes.json", read_pending());
+    check(&mut problems, "jogadores.json", read_players());
+    check(&mut problems, "nomes_historico.json", read_name_history());
+    check(&mut problems, "conversas.json", read_conversations());
+    check(&mut problems, "uso.json", read_usage());
+
+    problems
+}